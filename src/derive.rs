@@ -0,0 +1,119 @@
+//! Declarative registration of Rust types with field getters/setters, and running scripts over
+//! such types without a `serde` round-trip.
+
+use super::*;
+
+/// Re-exports used by macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use rhai;
+}
+
+/// Implement [`CustomType`][rhai::CustomType] for a Rust struct, exposing the listed fields to
+/// scripts via getters/setters.
+///
+/// Each field type must be a valid Rhai value type (i.e. `Clone + Send + Sync + 'static`).
+/// Register the type into the Rhai [`Engine`] via [`Engine::build_type`] in the engine setup
+/// closure passed to [`RhaiScriptBuilder::build_with_setup`], then run scripts over values of
+/// the type via [`RhaiScript::run_script_typed`].
+///
+/// An optional type name for scripts can be specified; it defaults to the Rust type name.
+///
+/// Requires the `derive` feature.
+///
+/// # Example
+///
+/// ```ignore
+/// use rhai_loco::script_type;
+///
+/// #[derive(Debug, Clone)]
+/// pub struct Order {
+///     pub id: i64,
+///     pub total: f64,
+///     pub note: String,
+/// }
+///
+/// script_type! { Order as "Order" { id: i64, total: f64, note: String } }
+///
+/// // In the engine setup closure:
+/// engine.build_type::<Order>();
+/// ```
+#[macro_export]
+macro_rules! script_type {
+    ($type:ty { $($field:ident : $field_type:ty),* $(,)? }) => {
+        $crate::script_type! { $type as stringify!($type) { $($field : $field_type),* } }
+    };
+    ($type:ty as $name:expr { $($field:ident : $field_type:ty),* $(,)? }) => {
+        impl $crate::derive::__private::rhai::CustomType for $type {
+            fn build(mut builder: $crate::derive::__private::rhai::TypeBuilder<Self>) {
+                builder.with_name($name);
+                $(
+                    builder.with_get_set(
+                        stringify!($field),
+                        |obj: &mut Self| -> $field_type { obj.$field.clone() },
+                        |obj: &mut Self, value: $field_type| obj.$field = value,
+                    );
+                )*
+            }
+        }
+    };
+}
+
+impl RhaiScript {
+    /// Run a script with a value of a custom type mapped to `this`.
+    ///
+    /// Unlike [`run_script`][RhaiScript::run_script], `data` is passed to the script directly as a
+    /// custom type instead of being converted via `serde`, so the type must have been registered
+    /// into the Rhai [`Engine`] (see [`script_type!`][crate::script_type]).
+    /// Mutations to `data` are written back only if the function call succeeds.
+    ///
+    /// Requires the `derive` feature.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the script changes `this` into a value of a different type.
+    pub fn run_script_typed<T: Variant + Clone>(
+        &self,
+        script_file: &str,
+        data: &mut T,
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        let options = RunOptions::new();
+
+        let result = self
+            .load_script_with_options(script_file, &options)
+            .and_then(|ast| {
+                let mut arg_values = Vec::new();
+                args.parse(&mut arg_values);
+
+                let type_name = self.engine().map_type_name(std::any::type_name::<T>());
+
+                debug!(fn_name, type_name, source = ast.source(), "Rhai: call function with custom type");
+
+                let mut obj = Dynamic::from(data.clone());
+                let value = self.call_ast_with_this(&options, script_file, &ast, &mut obj, fn_name, arg_values)?;
+
+                if !obj.is::<T>() {
+                    let typ = obj.type_name();
+                    return Err(EvalAltResult::ErrorSystem(
+                        format!("`this` changed from `{type_name}` to `{typ}` after calling `{fn_name}`"),
+                        "type mismatch".into(),
+                    )
+                    .into());
+                }
+                *data = obj.cast::<T>();
+
+                self.finish_result(&options, &value, fn_name)
+            });
+
+        if let Err(ref err) = result {
+            self.track_error(script_file, err);
+        }
+
+        result
+    }
+}
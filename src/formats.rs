@@ -0,0 +1,95 @@
+//! Parse/stringify functions for data formats other than JSON, registered into the Rhai
+//! [`Engine`] when the respective features are enabled, and binary serialization of script
+//! results.
+
+use super::*;
+
+/// Register parse/stringify functions for all enabled data formats into a Rhai [`Engine`].
+#[allow(unused_variables)]
+pub(crate) fn register_format_functions(engine: &mut Engine) {
+    #[cfg(feature = "yaml")]
+    {
+        engine.register_fn("yaml_parse", |s: &str| -> RhaiResult<Dynamic> {
+            serde_yaml::from_str(s).map_err(|err| format!("invalid YAML: {err}").into())
+        });
+        engine.register_fn("yaml_stringify", |value: Dynamic| -> RhaiResult<String> {
+            serde_yaml::to_string(&value)
+                .map_err(|err| format!("cannot convert to YAML: {err}").into())
+        });
+    }
+
+    #[cfg(feature = "toml")]
+    {
+        engine.register_fn("toml_parse", |s: &str| -> RhaiResult<Dynamic> {
+            toml::from_str(s).map_err(|err| format!("invalid TOML: {err}").into())
+        });
+        engine.register_fn("toml_stringify", |value: Dynamic| -> RhaiResult<String> {
+            toml::to_string(&value)
+                .map_err(|err| format!("cannot convert to TOML: {err}").into())
+        });
+    }
+}
+
+/// Binary format for serializing script results via [`RhaiScript::run_script_as_bytes`].
+///
+/// Each format requires its respective feature.
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SerFormat {
+    /// [MessagePack](https://msgpack.org), with object maps serialized as maps keyed by
+    /// field name. Requires the `msgpack` feature.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// [CBOR](https://cbor.io). Requires the `cbor` feature.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+impl RhaiScript {
+    /// Run a script, returning the result serialized in a binary [format][SerFormat] (e.g. for
+    /// internal RPC), without converting it into JSON first.
+    ///
+    /// Requires the `msgpack` or `cbor` feature.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    /// * Error if the result cannot be serialized.
+    pub fn run_script_as_bytes(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+        format: SerFormat,
+    ) -> RhaiResult<Vec<u8>> {
+        let value = self.run_script_dynamic(script_file, data, fn_name, args)?;
+
+        let result = match format {
+            #[cfg(feature = "msgpack")]
+            SerFormat::MessagePack => {
+                rmp_serde::to_vec_named(&value).map_err(|err| err.to_string())
+            }
+            #[cfg(feature = "cbor")]
+            SerFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(&value, &mut bytes)
+                    .map(|()| bytes)
+                    .map_err(|err| err.to_string())
+            }
+        };
+
+        result.map_err(|err| {
+            EvalAltResult::ErrorSystem(
+                format!("cannot serialize result of `{fn_name}` as {format:?}"),
+                err.into(),
+            )
+            .into()
+        })
+    }
+}
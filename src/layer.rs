@@ -0,0 +1,169 @@
+//! [Tower][tower] layer running a script over every outgoing JSON response.
+
+use super::*;
+use axum::body::{Body, HttpBody};
+use axum::http::{header, Request};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// [Tower][tower] layer that runs a script function over the body of every `application/json`
+/// response, with the parsed body mapped to `this`. The (possibly mutated) `this` becomes the
+/// new body.
+///
+/// Responses that are not JSON, that are streamed (of unknown size), or whose bodies are larger
+/// than the maximum body size pass through untouched. If the script fails, the response is
+/// replaced by an error response (via [`RhaiScript::convert_runtime_error`]) so that a failed
+/// transformation (e.g. redaction) never leaks the original body.
+///
+/// The global [`RhaiScript`] instance is used. If it does not exist, all responses pass through.
+///
+/// The script runs on Tokio's blocking thread pool via [`RhaiScript::run_script_async`], so it
+/// does not block the async worker threads, and it is subject to the
+/// [concurrency limit][RhaiScriptBuilder::with_max_concurrency] (if any). Over-limit responses
+/// are replaced by `503 Service Unavailable` errors under the
+/// [`FailFast`][BusyPolicy::FailFast] policy.
+///
+/// # Example
+///
+/// ```ignore
+/// router.layer(RhaiResponseLayer::new("responses", "transform").max_body_size(64 * 1024))
+/// ```
+#[derive(Debug, Clone)]
+pub struct RhaiResponseLayer {
+    /// Script file containing the function.
+    script_file: Arc<str>,
+    /// Name of the function to run.
+    fn_name: Arc<str>,
+    /// Maximum size (in bytes) of a response body to transform.
+    max_body_size: usize,
+}
+
+impl RhaiResponseLayer {
+    /// Default maximum size (in bytes) of a response body to transform.
+    pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+    /// Create a new [`RhaiResponseLayer`] running a function in a script file.
+    #[inline(always)]
+    #[must_use]
+    pub fn new(script_file: &str, fn_name: &str) -> Self {
+        Self {
+            script_file: script_file.into(),
+            fn_name: fn_name.into(),
+            max_body_size: Self::DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+    /// Set the maximum size (in bytes) of a response body to transform.
+    /// Default [`DEFAULT_MAX_BODY_SIZE`][Self::DEFAULT_MAX_BODY_SIZE].
+    #[inline(always)]
+    #[must_use]
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Transform a response.
+    pub(crate) async fn transform(&self, response: Response) -> Response {
+        let is_json = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.starts_with("application/json"));
+
+        let size = response.body().size_hint().upper();
+
+        let Some(script) = RhaiScript::try_get_instance() else {
+            return response;
+        };
+
+        if !is_json || size.map_or(true, |size| size > self.max_body_size as u64) {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+
+        let bytes = match axum::body::to_bytes(body, self.max_body_size).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!(target: ROOT, %err, "cannot read response body");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+
+        let Ok(value) = serde_json::from_slice::<Value>(&bytes) else {
+            return Response::from_parts(parts, Body::from(bytes));
+        };
+
+        let value = match script
+            .run_script_async(&self.script_file, value, &self.fn_name, Vec::new())
+            .await
+        {
+            Ok((_, value)) => value,
+            Err(err) => {
+                return script
+                    .convert_runtime_error::<Response>(err, |msg| Err(Error::string(&msg)))
+                    .unwrap_or_else(IntoResponse::into_response);
+            }
+        };
+
+        let _ = parts.headers.remove(header::CONTENT_LENGTH);
+
+        match serde_json::to_vec(&value) {
+            Ok(body) => Response::from_parts(parts, Body::from(body)),
+            Err(err) => {
+                warn!(target: ROOT, %err, "cannot serialize transformed response body");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for RhaiResponseLayer {
+    type Service = RhaiResponseService<S>;
+
+    #[inline(always)]
+    fn layer(&self, inner: S) -> Self::Service {
+        RhaiResponseService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// [Tower][tower] service created by [`RhaiResponseLayer`].
+#[derive(Debug, Clone)]
+pub struct RhaiResponseService<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: RhaiResponseLayer,
+}
+
+impl<S, B> Service<Request<B>> for RhaiResponseService<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Response, S::Error>> + Send>>;
+
+    #[inline(always)]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // Take the service that is ready, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let response = inner.call(request).await?;
+            Ok(layer.transform(response).await)
+        })
+    }
+}
@@ -1,8 +1,14 @@
 #![doc = include_str!("../README.md")]
 
 use ::serde::{de::DeserializeOwned, Deserialize, Serialize};
-use axum::{extract::FromRequestParts, http::request::Parts, Extension, Router as AxumRouter};
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Router as AxumRouter,
+};
 use loco_rs::app::{AppContext, Initializer};
+use loco_rs::controller::ErrorDetail;
 use loco_rs::prelude::*;
 use serde_json::Value;
 use std::{
@@ -10,16 +16,42 @@ use std::{
     fmt::Debug,
     fs::read_dir,
     path::{Path, PathBuf},
-    sync::{Arc, OnceLock, RwLock},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock, PoisonError, RwLock,
+    },
 };
-use tracing::{debug, info, trace, trace_span};
+use tracing::{debug, info, trace, trace_span, warn};
+
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod derive;
+mod formats;
+mod layer;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod macros;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(test)]
+mod tests;
+#[cfg(feature = "hot-reload")]
+mod watch;
 
 // Re-export useful Rhai types and functions.
-use rhai::module_resolvers::FileModuleResolver;
+use rhai::module_resolvers::{FileModuleResolver, ModuleResolversCollection};
 pub use rhai::serde::{from_dynamic, to_dynamic};
 pub use rhai::*;
 pub use tera;
 
+pub use layer::{RhaiResponseLayer, RhaiResponseService};
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+pub use formats::SerFormat;
+#[cfg(feature = "hot-reload")]
+pub use watch::ScriptWatch;
+
 /// Type alias for `Result<T, Box<EvalAltResult>>`.
 pub type RhaiResult<T> = std::result::Result<T, Box<EvalAltResult>>;
 
@@ -47,6 +79,324 @@ pub static RHAI_SCRIPT: OnceLock<RhaiScript> = OnceLock::new();
 /// Error message for script file not found.
 const SCRIPT_FILE_NOT_FOUND: &str = "script file not found";
 
+/// Error tag for scripts not run because scripting is [disabled][RhaiScript::set_enabled].
+pub const SCRIPTING_DISABLED: &str = "scripting_disabled";
+
+/// Error tag for scripts not run because the
+/// [maximum number of concurrent script calls][RhaiScriptBuilder::with_max_concurrency] is reached.
+pub const SCRIPTING_BUSY: &str = "scripting_busy";
+
+/// Error tag for scripts that exceeded the maximum number of operations allowed.
+pub const COMPUTE_BUDGET_EXCEEDED: &str = "compute_budget_exceeded";
+
+/// Error tag for scripts that exceeded a [memory limit][MemoryLimits], or for data passed to
+/// scripts that exceeded the [maximum input size][RhaiScriptBuilder::with_max_input_size].
+pub const MEMORY_LIMIT_EXCEEDED: &str = "memory_limit_exceeded";
+
+/// Error tag for scripts that failed via the `fail` script function.
+pub const SCRIPT_FAILED: &str = "script_failed";
+
+/// Convert a [Loco error][Error] into a [Rhai error][EvalAltResult].
+///
+/// This is useful inside native Rust functions registered into the Rhai [`Engine`] that call
+/// Loco APIs. The error message is preserved and categorized (e.g. `not found`, `unauthorized`).
+///
+/// This is the inverse of [`RhaiScript::convert_runtime_error`], which returns the original
+/// Loco error when it encounters an error converted by this function.
+///
+/// # Example
+///
+/// ```ignore
+/// engine.register_fn("find_user", move |id: INT| -> RhaiResult<Dynamic> {
+///     let user = block_on(users::Model::find_by_id(&db, id)).map_err(loco_err_to_rhai)?;
+///     to_dynamic(user)
+/// });
+/// ```
+#[must_use]
+pub fn loco_err_to_rhai(err: Error) -> Box<EvalAltResult> {
+    let category = match err {
+        Error::NotFound => "not found",
+        Error::Unauthorized(..) => "unauthorized",
+        Error::BadRequest(..) => "bad request",
+        Error::CustomError(..) => "custom error",
+        _ => "error",
+    };
+
+    EvalAltResult::ErrorSystem(category.to_string(), err.into()).into()
+}
+
+/// Convert a Rhai object map into a JSON object, recursively.
+///
+/// Values that cannot be represented in JSON (e.g. custom types, function pointers, `NaN`)
+/// become `null`.
+#[inline(always)]
+#[must_use]
+pub fn rhai_map_to_json(map: &Map) -> Value {
+    Value::Object(
+        map.iter()
+            .map(|(k, v)| (k.to_string(), from_dynamic(v).unwrap_or(Value::Null)))
+            .collect(),
+    )
+}
+
+/// Convert a JSON object into a Rhai object map, recursively.
+///
+/// Non-object JSON values (e.g. arrays or strings) yield an empty object map.
+#[must_use]
+pub fn json_to_rhai_map(value: &Value) -> Map {
+    match value {
+        Value::Object(obj) => obj
+            .iter()
+            .map(|(k, v)| (k.as_str().into(), to_dynamic(v).unwrap_or_default()))
+            .collect(),
+        _ => Map::new(),
+    }
+}
+
+/// Extract a [Loco error][Error] converted via [`loco_err_to_rhai`], if any, from a
+/// [Rhai error][EvalAltResult] (possibly nested within function calls).
+///
+/// The original error is returned if it does not contain a Loco error.
+fn into_loco_error(err: Box<EvalAltResult>) -> std::result::Result<Error, Box<EvalAltResult>> {
+    match *err {
+        EvalAltResult::ErrorSystem(msg, e) => match e.downcast::<Error>() {
+            Ok(e) => Ok(*e),
+            Err(e) => Err(EvalAltResult::ErrorSystem(msg, e).into()),
+        },
+        EvalAltResult::ErrorInFunctionCall(f, src, e, pos) => into_loco_error(e)
+            .map_err(|e| EvalAltResult::ErrorInFunctionCall(f, src, e, pos).into()),
+        EvalAltResult::ErrorInModule(m, e, pos) => {
+            into_loco_error(e).map_err(|e| EvalAltResult::ErrorInModule(m, e, pos).into())
+        }
+        e => Err(e.into()),
+    }
+}
+
+/// Format script errors (e.g. from [`RhaiScript::validate_all`]) into a human-readable report,
+/// one error per line in the form `path:line:column: error`.
+#[must_use]
+pub fn format_script_errors(errors: &[(PathBuf, Box<EvalAltResult>)]) -> String {
+    errors
+        .iter()
+        .map(|(path, err)| {
+            let pos = err.position();
+            let location = match (pos.line(), pos.position()) {
+                (Some(line), Some(col)) => format!(":{line}:{col}"),
+                (Some(line), None) => format!(":{line}"),
+                _ => String::new(),
+            };
+            // The position is already in the location
+            let message = err.to_string();
+            let message = message
+                .strip_suffix(&format!(" ({pos})"))
+                .unwrap_or(&message);
+            format!("{}{location}: {message}", path.to_string_lossy())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Find an [`ErrorTooManyOperations`][EvalAltResult::ErrorTooManyOperations] error nested
+/// within function calls, returning the innermost script source (if any) and the position.
+fn find_too_many_operations<'a>(
+    err: &'a EvalAltResult,
+    source: Option<&'a str>,
+) -> Option<(Option<&'a str>, Position)> {
+    match err {
+        EvalAltResult::ErrorTooManyOperations(pos) => Some((source, *pos)),
+        EvalAltResult::ErrorInFunctionCall(_, src, e, _) => {
+            find_too_many_operations(e, Some(src.as_str()).filter(|s| !s.is_empty()).or(source))
+        }
+        EvalAltResult::ErrorInModule(_, e, _) => find_too_many_operations(e, source),
+        _ => None,
+    }
+}
+
+/// Find an [`ErrorDataTooLarge`][EvalAltResult::ErrorDataTooLarge] error nested within function
+/// calls, returning the kind of data and the position.
+fn find_data_too_large(err: &EvalAltResult) -> Option<(&str, Position)> {
+    match err {
+        EvalAltResult::ErrorDataTooLarge(typ, pos) => Some((typ.as_str(), *pos)),
+        EvalAltResult::ErrorInFunctionCall(_, _, e, _) | EvalAltResult::ErrorInModule(_, e, _) => {
+            find_data_too_large(e)
+        }
+        _ => None,
+    }
+}
+
+/// Limits on the sizes of data created by scripts, set via
+/// [`RhaiScriptBuilder::with_memory_limits`].
+///
+/// Scripts exceeding a limit fail with an error, converted by
+/// [`RhaiScript::convert_runtime_error`] into an error tagged [`MEMORY_LIMIT_EXCEEDED`].
+///
+/// See [`Engine::set_max_array_size`], [`Engine::set_max_map_size`] and
+/// [`Engine::set_max_string_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct MemoryLimits {
+    /// Maximum number of items in an array or BLOB, if any. Default `None`.
+    pub max_array_size: Option<usize>,
+    /// Maximum number of properties in an object map, if any. Default `None`.
+    pub max_map_size: Option<usize>,
+    /// Maximum length (in bytes) of a string, if any. Default `None`.
+    pub max_string_size: Option<usize>,
+}
+
+impl MemoryLimits {
+    /// Create a new [`MemoryLimits`] with no limits.
+    #[inline(always)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a new [`MemoryLimits`] with limits suitable for untrusted scripts in
+    /// public-facing endpoints: 10,000 array items, 1,000 object map properties and
+    /// 1 MB strings.
+    #[inline(always)]
+    #[must_use]
+    pub fn strict() -> Self {
+        Self {
+            max_array_size: Some(10_000),
+            max_map_size: Some(1_000),
+            max_string_size: Some(1024 * 1024),
+        }
+    }
+    /// Set the maximum number of items in an array or BLOB.
+    #[inline(always)]
+    #[must_use]
+    pub fn max_array_size(mut self, size: usize) -> Self {
+        self.max_array_size = Some(size);
+        self
+    }
+    /// Set the maximum number of properties in an object map.
+    #[inline(always)]
+    #[must_use]
+    pub fn max_map_size(mut self, size: usize) -> Self {
+        self.max_map_size = Some(size);
+        self
+    }
+    /// Set the maximum length (in bytes) of a string.
+    #[inline(always)]
+    #[must_use]
+    pub fn max_string_size(mut self, size: usize) -> Self {
+        self.max_string_size = Some(size);
+        self
+    }
+}
+
+/// Set up a Rhai [`Engine`] with the functions and callbacks available to all scripts, before any
+/// options or custom setup: `print` and `debug` routed through `output`, the data format
+/// functions, `fail`, `progress`, per-call limits (chained with `on_progress`, if any) and
+/// `shared` returning `shared_state`.
+fn register_defaults(
+    engine: &mut Engine,
+    output: &Arc<OutputHandlers>,
+    shared_state: &Arc<RwLock<Dynamic>>,
+    on_progress: Option<ProgressHook>,
+) {
+    output.install(engine);
+
+    formats::register_format_functions(engine);
+    register_fail(engine);
+    register_progress(engine);
+    register_call_limits(engine, on_progress);
+
+    // The state is a shared value, so this does not copy it
+    let state = shared_state.clone();
+    engine.register_fn("shared", move || {
+        state.read().unwrap_or_else(PoisonError::into_inner).clone()
+    });
+}
+
+/// Convert state shared by all scripts into a read-only shared value, so that it is not copied by
+/// each call to `shared()`.
+fn into_shared_state(state: Dynamic) -> Dynamic {
+    state.into_read_only().into_shared()
+}
+
+/// Register the `fail(status, message)` script function into an [`Engine`].
+///
+/// `fail` throws an object map `#{ status: INT, message: string }` which
+/// [`RhaiScript::convert_runtime_error`] turns into an HTTP error with that status.
+fn register_fail(engine: &mut Engine) {
+    engine.register_fn(
+        "fail",
+        |ctx: NativeCallContext, status: INT, message: &str| -> RhaiResult<Dynamic> {
+            if !(400..=599).contains(&status) {
+                return Err(format!("invalid error status code: {status}").into());
+            }
+
+            let mut map = Map::new();
+            let _ = map.insert("status".into(), status.into());
+            let _ = map.insert("message".into(), message.into());
+            Err(EvalAltResult::ErrorRuntime(map.into(), ctx.position()).into())
+        },
+    );
+}
+
+/// Find a script failure (thrown via the `fail` script function) nested within function calls,
+/// returning the status code and the message.
+fn find_script_failure(err: &EvalAltResult) -> Option<(StatusCode, String)> {
+    match err {
+        EvalAltResult::ErrorRuntime(value, _) => {
+            let map = value.read_lock::<Map>()?;
+            let status = map.get("status")?.as_int().ok()?;
+            let message = map.get("message")?.clone().into_string().ok()?;
+            let status = u16::try_from(status)
+                .ok()
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .filter(|s| s.is_client_error() || s.is_server_error())?;
+            Some((status, message))
+        }
+        EvalAltResult::ErrorInFunctionCall(_, _, e, _) | EvalAltResult::ErrorInModule(_, e, _) => {
+            find_script_failure(e)
+        }
+        _ => None,
+    }
+}
+
+/// Routes toggling script execution via [`RhaiScript::set_enabled`]:
+///
+/// * `GET /` returns the current state, e.g. `{ "enabled": true }`
+/// * `POST /enable` enables script execution
+/// * `POST /disable` disables script execution
+///
+/// All routes return the (new) state. If no [`RhaiScript`] instance exists, they return
+/// `503 Service Unavailable`.
+///
+/// The routes are unprotected; nest them under a path guarded by the application's
+/// authentication.
+///
+/// # Example
+///
+/// ```ignore
+/// // In the application's `Hooks` implementation
+/// async fn after_routes(router: AxumRouter, _ctx: &AppContext) -> Result<AxumRouter> {
+///     Ok(router.nest("/admin/scripting", rhai_loco::kill_switch_routes()))
+/// }
+/// ```
+pub fn kill_switch_routes() -> AxumRouter {
+    use axum::routing::{get, post};
+
+    fn switch(enabled: Option<bool>) -> Response {
+        match RhaiScript::try_get_instance() {
+            Some(script) => {
+                if let Some(enabled) = enabled {
+                    script.set_enabled(enabled);
+                }
+                axum::Json(serde_json::json!({ "enabled": script.is_enabled() })).into_response()
+            }
+            None => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+        }
+    }
+
+    AxumRouter::new()
+        .route("/", get(|| async { switch(None) }))
+        .route("/enable", post(|| async { switch(Some(true)) }))
+        .route("/disable", post(|| async { switch(Some(false)) }))
+}
+
 /// Type that wraps a scripting engine for use in [`Axum`][axum] handlers.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ScriptingEngine<E>(pub E);
@@ -85,213 +435,4862 @@ where
     }
 }
 
-/// A scripting engine based on [`Rhai`](https://rhai.rs).
-#[derive(Debug, Clone)]
-pub struct RhaiScript {
-    /// Path to the directory containing Rhai scripts.
-    scripts_path: Arc<PathBuf>,
-    /// Cache of compiled Rhai scripts in [`AST`] form.
-    cache: Arc<RwLock<HashMap<PathBuf, Arc<AST>>>>,
+/// Registry of [`RhaiScript`] instances per tenant, selected by a tenant ID extracted from
+/// each request.
+///
+/// Install it as an [`Extension`] layer and use the [`TenantScriptingEngine`] extractor in
+/// handlers. Tenant instances are typically created via [`RhaiScript::fork`].
+///
+/// # Example
+///
+/// ```ignore
+/// let script = RhaiScript::get_instance();
+///
+/// let registry = ScriptingEngineRegistry::new(|parts| {
+///     parts.headers.get("x-tenant-id")?.to_str().ok().map(Into::into)
+/// })
+/// .with_tenant("acme", script.fork("assets/tenants/acme")?)
+/// .with_default(script);
+///
+/// router.layer(Extension(registry))
+/// ```
+#[derive(Clone)]
+pub struct ScriptingEngineRegistry {
+    /// [`RhaiScript`] instances keyed by tenant ID.
+    tenants: HashMap<String, RhaiScript>,
+    /// [`RhaiScript`] instance for requests without a known tenant ID, if any.
+    default: Option<RhaiScript>,
+    /// Function extracting the tenant ID from a request.
+    tenant_id: Arc<dyn Fn(&Parts) -> Option<String> + Send + Sync>,
 }
 
-impl RhaiScript {
-    /// File extension for Rhai scripts.
-    pub const SCRIPTS_EXT: &'static str = "rhai";
+impl Debug for ScriptingEngineRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptingEngineRegistry")
+            .field("tenants", &self.tenants.keys().collect::<Vec<_>>())
+            .field("default", &self.default.is_some())
+            .finish_non_exhaustive()
+    }
+}
 
-    /// Get a new [`RhaiScript`] instance.
-    ///
-    /// The methods [`new`][`RhaiScript::new`] or [`new_with_setup`][`RhaiScript::new_with_setup`] must be called first.
-    ///
-    /// # Panics
-    ///
-    /// Panics if called before [`new`][`RhaiScript::new`] or [`new_with_setup`][`RhaiScript::new_with_setup`].
+impl ScriptingEngineRegistry {
+    /// Create a new, empty [`ScriptingEngineRegistry`] with a function extracting the tenant ID
+    /// from a request.
     #[inline(always)]
-    pub fn get_instance() -> Self {
-        RHAI_SCRIPT.get().unwrap().clone()
+    #[must_use]
+    pub fn new(tenant_id: impl Fn(&Parts) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self {
+            tenants: HashMap::new(),
+            default: None,
+            tenant_id: Arc::new(tenant_id),
+        }
+    }
+    /// Add the [`RhaiScript`] instance for a tenant.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_tenant(mut self, tenant_id: impl Into<String>, script: RhaiScript) -> Self {
+        let _ = self.tenants.insert(tenant_id.into(), script);
+        self
+    }
+    /// Set the [`RhaiScript`] instance for requests without a known tenant ID.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_default(mut self, script: RhaiScript) -> Self {
+        self.default = Some(script);
+        self
+    }
+    /// Get the [`RhaiScript`] instance for a tenant, falling back to the default (if any).
+    #[must_use]
+    pub fn get(&self, tenant_id: Option<&str>) -> Option<&RhaiScript> {
+        tenant_id
+            .and_then(|id| self.tenants.get(id))
+            .or(self.default.as_ref())
     }
+}
 
-    /// Create a new [`RhaiScript`] instance.
+/// Extractor of the [`RhaiScript`] instance for the tenant of a request, from the
+/// [`ScriptingEngineRegistry`] installed as an [`Extension`] layer.
+///
+/// Requests for an unknown tenant (with no default instance) are rejected with
+/// `404 Not Found`.
+#[derive(Debug, Clone)]
+pub struct TenantScriptingEngine(pub RhaiScript);
+
+impl<S: Send + Sync> FromRequestParts<S> for TenantScriptingEngine {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let Extension(registry): Extension<ScriptingEngineRegistry> =
+            Extension::from_request_parts(parts, state)
+                .await
+                .expect("Scripting engine registry missing. Is it installed?");
+
+        let tenant_id = (registry.tenant_id)(parts);
+
+        match registry.get(tenant_id.as_deref()) {
+            Some(script) => Ok(Self(script.clone())),
+            None => {
+                debug!(target: ROOT, ?tenant_id, "no scripting engine for tenant");
+                Err(Error::NotFound)
+            }
+        }
+    }
+}
+
+/// Type of a callback that receives the output of the Rhai `print` statement.
+pub type PrintCallback = Box<dyn Fn(&str) + Send + Sync + 'static>;
+
+/// Type of a callback that receives the output of the Rhai `debug` statement,
+/// together with the source of the script (if any) and the position of the statement.
+pub type DebugCallback = Box<dyn Fn(&str, Option<&str>, Position) + Send + Sync + 'static>;
+
+/// Swappable handlers for the Rhai `print` and `debug` statements.
+///
+/// When no handler is installed, output is sent to the log.
+#[derive(Default)]
+struct OutputHandlers {
+    /// Custom handler for `print`, if any.
+    print: RwLock<Option<PrintCallback>>,
+    /// Custom handler for `debug`, if any.
+    debug: RwLock<Option<DebugCallback>>,
+}
+
+impl Debug for OutputHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputHandlers")
+            .field(
+                "print",
+                &self.print.read().unwrap_or_else(PoisonError::into_inner).is_some(),
+            )
+            .field(
+                "debug",
+                &self.debug.read().unwrap_or_else(PoisonError::into_inner).is_some(),
+            )
+            .finish()
+    }
+}
+
+impl OutputHandlers {
+    /// Install the `print` and `debug` callbacks of a Rhai [`Engine`] to route through these handlers.
+    fn install(self: &Arc<Self>, engine: &mut Engine) {
+        let handlers = self.clone();
+        engine.on_print(move |message| {
+            match *handlers.print.read().unwrap_or_else(PoisonError::into_inner) {
+                Some(ref f) => f(message),
+                None => info!(target: ROOT, message),
+            }
+        });
+
+        let handlers = self.clone();
+        engine.on_debug(move |message, source, pos| {
+            match *handlers.debug.read().unwrap_or_else(PoisonError::into_inner) {
+                Some(ref f) => f(message, source, pos),
+                None => debug!(target: ROOT, ?message, source, position = ?pos),
+            }
+        });
+    }
+}
+
+/// Options for running a script via [`RhaiScript::run_script_with_options`].
+///
+/// # Per-call limits
+///
+/// Only the number of operations ([`max_operations`][RunOptions::max_operations]) and the running
+/// time ([`timeout`][RunOptions::timeout]) can be limited per call. Other limits (e.g.
+/// [memory limits][MemoryLimits], expression depths, call-stack depth) are engine-wide and
+/// shared by all calls.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct RunOptions {
+    /// Allow calling [private][FnAccess::Private] functions. Default `false`.
+    pub allow_private: bool,
+    /// Optimization level to apply when the script is compiled, if different from the
+    /// [`Engine`]'s. Default `None`.
     ///
-    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    /// Since compiled scripts are cached, this only takes effect when the script is not yet
+    /// in the cache.
     ///
-    /// # Panics
+    /// Notice that this level is applied _on top of_ optimizations already performed during
+    /// compilation at the [`Engine`]'s optimization level, which cannot be reversed. For full
+    /// control, set the [`Engine`]'s optimization level to [`OptimizationLevel::None`] and
+    /// request higher levels per script.
+    pub optimization_level: Option<OptimizationLevel>,
+    /// What to do if the script changes `this` into a value of a different type.
+    /// Default [`ThisTypeMismatch::Error`].
+    pub this_type_mismatch: ThisTypeMismatch,
+    /// Sort the keys of all object maps (recursively) in the returned JSON value, for
+    /// deterministic output. Default `false`.
     ///
-    /// Panics if called more than once.
+    /// Sorting rebuilds every object in the result, which has a cost proportional to the size
+    /// of the result. Avoid it for large results unless deterministic output is required.
+    pub sort_map_keys: bool,
+    /// Convert floating-point numbers with no fractional part (e.g. `3.0`) in `data` into
+    /// integers (e.g. `3`) before passing it to the script. Default `false`, which keeps them
+    /// as floating-point numbers.
     ///
-    /// # Errors
+    /// For example, with `data` being `{ "price": 3.0 }`, `type_of(this.price)` in script is
+    /// `"f64"` by default, but `"i64"` with this option on. Integers in `this` are converted back
+    /// into floating-point numbers for floating-point fields when `data` is written back.
+    pub integral_floats_as_int: bool,
+    /// How a unit `()` result (e.g. from a function that returns nothing) maps to JSON.
+    /// Default [`UnitResult::Null`].
+    pub unit_result: UnitResult,
+    /// Channel receiving [progress events][ScriptProgress] reported by the script via the
+    /// `progress(percent, message)` function, if any. Default `None`, which makes `progress`
+    /// a no-op.
+    pub progress: Option<tokio::sync::mpsc::Sender<ScriptProgress>>,
+    /// Return an error if the result contains a non-finite floating-point number (`NaN` or
+    /// infinity), which JSON cannot represent. Default `false`, which converts such numbers
+    /// into `null`.
+    pub reject_non_finite: bool,
+    /// Maximum number of operations allowed for this call, if any. Default `None`.
     ///
-    /// Error if the scripts directory does not exist.
-    #[inline(always)]
-    pub fn new(scripts_path: impl Into<PathBuf>) -> Result<Self> {
-        Self::new_with_setup(scripts_path, |_| {})
-    }
-
-    /// Create a new [`RhaiScript`] instance with custom setup.
+    /// This is enforced in addition to the [`Engine`]'s limit (see
+    /// [`Engine::set_max_operations`]), so it can only tighten, not relax, the engine-wide limit.
+    /// Keep the engine-wide limit unset (or generous) and set per-call budgets for untrusted
+    /// scripts.
     ///
-    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    /// This is enforced via the [`Engine`]'s progress callback, so calling
+    /// [`Engine::on_progress`] in a custom engine setup (e.g. in
+    /// [`build_with_setup`][RhaiScriptBuilder::build_with_setup]) disables it. Use
+    /// [`RhaiScriptBuilder::on_progress`] instead.
+    pub max_operations: Option<u64>,
+    /// Maximum time allowed for this call, if any. Default `None`.
     ///
-    /// # Panics
+    /// Time is checked between operations, so a long-running native function called by the
+    /// script is not interrupted.
     ///
-    /// Panics if called more than once.
+    /// As with [`max_operations`][RunOptions::max_operations], calling [`Engine::on_progress`]
+    /// in a custom engine setup disables this. Use [`RhaiScriptBuilder::on_progress`] instead.
+    pub timeout: Option<std::time::Duration>,
+    /// [Tracing][tracing] span to run the script within, if any. Default `None`, which runs the
+    /// script within the caller's current span.
     ///
-    /// # Errors
+    /// Events emitted by the script (e.g. via `print`, `debug` or logging functions) are
+    /// recorded within this span, so they carry the trace/span IDs of the request.
+    pub parent_span: Option<tracing::Span>,
+    /// Custom per-call state available to native functions via [`NativeCallContext::tag`],
+    /// if any. Default `None`, which uses the [`Engine`]'s default tag.
+    pub tag: Option<Dynamic>,
+    /// Check the arguments against the parameters of the function before calling it, logging a
+    /// warning for each suspicious argument. Default `false`.
     ///
-    /// Error if the scripts directory does not exist.
-    pub fn new_with_setup(
-        scripts_path: impl Into<PathBuf>,
-        setup: impl FnOnce(&mut Engine),
-    ) -> Result<Self> {
-        let scripts_path = scripts_path.into();
+    /// The number of arguments is always checked. Since parameters of script functions are
+    /// untyped, this additionally only flags arguments that are `()` (e.g. from a `None`), which
+    /// usually indicate an integration error.
+    pub check_args: bool,
+}
 
-        if !scripts_path.exists() {
-            return Err(Error::string(&format!(
-                "missing scripts directory: `{}`",
-                scripts_path.to_string_lossy()
-            )));
-        }
+/// Progress event reported by a script via the `progress(percent, message)` function.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScriptProgress {
+    /// Percentage of completion.
+    pub percent: FLOAT,
+    /// Progress message.
+    pub message: String,
+}
 
-        let mut engine = Engine::new();
+thread_local! {
+    /// Channel receiving progress events from the script currently running on this thread.
+    static PROGRESS: std::cell::RefCell<Option<tokio::sync::mpsc::Sender<ScriptProgress>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Register the `progress(percent, message)` script function into an [`Engine`].
+///
+/// Progress events are sent without blocking; they are dropped if the channel is full or closed.
+fn register_progress(engine: &mut Engine) {
+    fn send(percent: FLOAT, message: &str) {
+        PROGRESS.with(|progress| {
+            if let Some(ref tx) = *progress.borrow() {
+                let event = ScriptProgress {
+                    percent,
+                    message: message.to_string(),
+                };
+                if tx.try_send(event).is_err() {
+                    trace!(target: ROOT, percent, message, "drop progress event");
+                }
+            }
+        });
+    }
 
-        let mut resolver = FileModuleResolver::new_with_path(SCRIPTS_DIR);
-        resolver.enable_cache(false);
+    engine
+        .register_fn("progress", |percent: FLOAT, message: &str| send(percent, message))
+        .register_fn("progress", |percent: INT, message: &str| send(percent as FLOAT, message));
+}
 
-        engine
-            .set_module_resolver(resolver)
-            .on_print(|message| info!(target: ROOT, message))
-            .on_debug(
-                |message, source, pos| debug!(target: ROOT, ?message, source, position = ?pos),
-            );
+/// Check arguments against the parameters of a script function, logging a warning for each
+/// suspicious argument.
+fn check_args(ast: &AST, fn_name: &str, arg_values: &[Dynamic]) {
+    let Some(f) = ast
+        .iter_functions()
+        .find(|f| f.name == fn_name && f.params.len() == arg_values.len())
+    else {
+        return;
+    };
 
-        setup(&mut engine);
+    for (param, arg) in f.params.iter().zip(arg_values) {
+        if arg.is_unit() {
+            warn!(target: ROOT, fn_name, param, source = ast.source(), "argument is `()`");
+        }
+    }
+}
 
-        ENGINE
-            .set(engine)
-            .expect("`RhaiScript::new` or `RhaiScript::new_with_setup` can be called only once.");
+impl ConcurrencyLimit {
+    /// Acquire a permit to run a script, according to the [policy][BusyPolicy].
+    async fn acquire(&self, script_file: &str) -> RhaiResult<tokio::sync::OwnedSemaphorePermit> {
+        let permit = match self.policy {
+            BusyPolicy::Queue => self.semaphore.clone().acquire_owned().await.ok(),
+            BusyPolicy::FailFast => self.semaphore.clone().try_acquire_owned().ok(),
+        };
 
-        RHAI_SCRIPT
-            .set(Self {
-                scripts_path: Arc::new(scripts_path),
-                cache: Arc::new(RwLock::new(HashMap::new())),
-            })
-            .unwrap();
+        permit.ok_or_else(|| {
+            debug!(target: ROOT, script = script_file, max = self.max, "too many scripts running");
+            EvalAltResult::ErrorSystem(
+                SCRIPTING_BUSY.to_string(),
+                format!("maximum {} concurrent script calls", self.max).into(),
+            )
+            .into()
+        })
+    }
+}
 
-        Ok(Self::get_instance())
+/// Guard counting a running script call, for [`RhaiScript::in_flight`].
+struct InFlightGuard(Arc<std::sync::atomic::AtomicUsize>);
+
+impl InFlightGuard {
+    /// Count a running script call until the guard is dropped.
+    fn new(counter: &Arc<std::sync::atomic::AtomicUsize>) -> Self {
+        let _ = counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter.clone())
     }
+}
 
-    /// Get a reference to the Rhai [`Engine`].
-    #[inline(always)]
-    #[must_use]
-    pub fn engine(&self) -> &Engine {
-        ENGINE.get().unwrap()
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let _ = self.0.fetch_sub(1, Ordering::Relaxed);
     }
+}
 
-    /// Convert a [Rhai error][EvalAltResult] to a [Loco error][Result].
-    ///
-    /// If the error is a [runtime error][EvalAltResult::ErrorRuntime],
-    /// it is converted using the provided closure.
-    ///
-    /// Otherwise, the error is converted via [`Error::msg`].
-    pub fn convert_runtime_error<T>(
-        &self,
-        err: Box<EvalAltResult>,
-        converter: impl FnOnce(String) -> Result<T>,
-    ) -> Result<T> {
-        match *err {
-            EvalAltResult::ErrorRuntime(r, _) => converter(r.to_string()),
-            e => Err(Error::msg(e)),
+/// Block the current thread on a future, outside of any async runtime.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    /// Waker unparking the blocked thread.
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
         }
     }
 
-    /// Run a script if it exists.
-    ///
-    /// Return `Value::Null` if the script does not exist.
-    ///
-    /// # Errors
-    ///
-    /// * Error if there is a syntax error during compilation.
-    /// * Error if there is an error during script evaluation.
-    #[inline(always)]
-    pub fn run_script_if_exists(
-        &self,
-        script_file: &str,
-        data: &mut (impl Serialize + DeserializeOwned + Debug),
-        fn_name: &str,
-        args: impl FuncArgs,
+    let waker = std::task::Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+
+    loop {
+        match std::future::Future::poll(future.as_mut(), &mut cx) {
+            std::task::Poll::Ready(output) => return output,
+            std::task::Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// Per-call limits of the script currently running on a thread.
+#[derive(Debug, Clone, Copy, Default)]
+struct CallLimits {
+    /// Maximum number of operations, if any.
+    max_operations: Option<u64>,
+    /// Deadline, if any.
+    deadline: Option<std::time::Instant>,
+}
+
+/// Termination token of a script that exceeded a [per-call limit][CallLimits], holding the
+/// description of the limit.
+#[derive(Debug, Clone)]
+struct CallLimitExceeded(String);
+
+thread_local! {
+    /// Per-call limits of the script currently running on this thread.
+    static CALL_LIMITS: std::cell::Cell<CallLimits> =
+        const { std::cell::Cell::new(CallLimits { max_operations: None, deadline: None }) };
+}
+
+/// Install the enforcement of [per-call limits][CallLimits] into an [`Engine`] via
+/// [`Engine::on_progress`], followed by the progress callback set via
+/// [`RhaiScriptBuilder::on_progress`], if any.
+///
+/// Installing another progress callback (e.g. in an engine setup closure) disables per-call
+/// limits.
+fn register_call_limits(engine: &mut Engine, on_progress: Option<ProgressHook>) {
+    engine.on_progress(move |operations| {
+        let limits = CALL_LIMITS.with(std::cell::Cell::get);
+
+        match limits.max_operations {
+            Some(max) if operations > max => {
+                let token = CallLimitExceeded(format!("compute budget of {max} operations"));
+                return Some(Dynamic::from(token));
+            }
+            _ => (),
+        }
+        match limits.deadline {
+            Some(deadline) if std::time::Instant::now() > deadline => {
+                let token = CallLimitExceeded("time limit".to_string());
+                return Some(Dynamic::from(token));
+            }
+            _ => (),
+        }
+
+        on_progress
+            .as_ref()
+            .and_then(|ProgressHook(hook)| hook(operations))
+    });
+}
+
+/// Find a script termination due to a [per-call limit][CallLimits] nested within function calls,
+/// returning the description of the limit and the position.
+fn find_call_limit_exceeded(err: &EvalAltResult) -> Option<(String, Position)> {
+    match err {
+        EvalAltResult::ErrorTerminated(token, pos) if token.is::<CallLimitExceeded>() => {
+            Some((token.clone().cast::<CallLimitExceeded>().0, *pos))
+        }
+        EvalAltResult::ErrorInFunctionCall(_, _, e, _) | EvalAltResult::ErrorInModule(_, e, _) => {
+            find_call_limit_exceeded(e)
+        }
+        _ => None,
+    }
+}
+
+/// How a unit `()` result of a script maps to JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum UnitResult {
+    /// Map to `null`. This is the default.
+    #[default]
+    Null,
+    /// Map to an empty object `{}`.
+    EmptyObject,
+    /// Omit the result entirely.
+    ///
+    /// [`run_script_to_response_with_options`][RhaiScript::run_script_to_response_with_options]
+    /// returns `204 No Content` with an empty body. Where a [`Value`] must be returned
+    /// (e.g. [`run_script_with_options`][RhaiScript::run_script_with_options]), this is the same
+    /// as [`Null`][UnitResult::Null].
+    Absent,
+}
+
+/// Policy for when a script changes `this` into a value of a different type
+/// (e.g. from an object-map into an integer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ThisTypeMismatch {
+    /// Accept the new value if it can be converted back into the type of `data`,
+    /// otherwise return a descriptive error.
+    Convert,
+    /// Return a descriptive error if the type of `this` changed, even if the new value can be
+    /// converted back into the type of `data`. This is the default.
+    #[default]
+    Error,
+    /// Ignore the change and keep the original `data` if the type of `this` changed or if the
+    /// new value cannot be converted back into the type of `data`.
+    Ignore,
+}
+
+impl RunOptions {
+    /// Create a new [`RunOptions`] with default values.
+    #[inline(always)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Allow calling [private][FnAccess::Private] functions.
+    #[inline(always)]
+    #[must_use]
+    pub fn allow_private(mut self, value: bool) -> Self {
+        self.allow_private = value;
+        self
+    }
+    /// Set the optimization level to apply when the script is compiled.
+    #[inline(always)]
+    #[must_use]
+    pub fn optimization_level(mut self, level: OptimizationLevel) -> Self {
+        self.optimization_level = Some(level);
+        self
+    }
+    /// Set the policy for when the script changes `this` into a value of a different type.
+    #[inline(always)]
+    #[must_use]
+    pub fn this_type_mismatch(mut self, policy: ThisTypeMismatch) -> Self {
+        self.this_type_mismatch = policy;
+        self
+    }
+    /// Sort the keys of all object maps in the returned JSON value.
+    #[inline(always)]
+    #[must_use]
+    pub fn sort_map_keys(mut self, value: bool) -> Self {
+        self.sort_map_keys = value;
+        self
+    }
+    /// Convert floating-point numbers with no fractional part in `data` into integers.
+    #[inline(always)]
+    #[must_use]
+    pub fn integral_floats_as_int(mut self, value: bool) -> Self {
+        self.integral_floats_as_int = value;
+        self
+    }
+    /// Set how a unit `()` result maps to JSON.
+    #[inline(always)]
+    #[must_use]
+    pub fn unit_result(mut self, unit_result: UnitResult) -> Self {
+        self.unit_result = unit_result;
+        self
+    }
+    /// Set the channel receiving [progress events][ScriptProgress] reported by the script.
+    ///
+    /// Events are sent without blocking, so they are dropped if the channel is full.
+    #[inline(always)]
+    #[must_use]
+    pub fn progress(mut self, tx: tokio::sync::mpsc::Sender<ScriptProgress>) -> Self {
+        self.progress = Some(tx);
+        self
+    }
+    /// Return an error if the result contains a non-finite floating-point number.
+    #[inline(always)]
+    #[must_use]
+    pub fn reject_non_finite(mut self, value: bool) -> Self {
+        self.reject_non_finite = value;
+        self
+    }
+    /// Set the maximum number of operations allowed for this call.
+    #[inline(always)]
+    #[must_use]
+    pub fn max_operations(mut self, operations: u64) -> Self {
+        self.max_operations = Some(operations);
+        self
+    }
+    /// Set the maximum time allowed for this call.
+    #[inline(always)]
+    #[must_use]
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// Set the [tracing][tracing] span to run the script within.
+    #[inline(always)]
+    #[must_use]
+    pub fn parent_span(mut self, span: tracing::Span) -> Self {
+        self.parent_span = Some(span);
+        self
+    }
+    /// Set the custom per-call state available to native functions via
+    /// [`NativeCallContext::tag`].
+    #[inline(always)]
+    #[must_use]
+    pub fn tag(mut self, tag: impl Into<Dynamic>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+    /// Check the arguments against the parameters of the function before calling it.
+    #[inline(always)]
+    #[must_use]
+    pub fn check_args(mut self, value: bool) -> Self {
+        self.check_args = value;
+        self
+    }
+}
+
+/// Find a non-finite floating-point number (`NaN` or infinity) within a [`Dynamic`] value,
+/// recursively, returning the path to it (e.g. `.items[2].price`), if any.
+fn find_non_finite(value: &Dynamic, path: &mut String) -> Option<(String, FLOAT)> {
+    if let Ok(f) = value.as_float() {
+        return (!f.is_finite()).then(|| (path.clone(), f));
+    }
+
+    let len = path.len();
+
+    let found = if let Some(map) = value.read_lock::<Map>() {
+        map.iter().find_map(|(k, v)| {
+            path.truncate(len);
+            path.push('.');
+            path.push_str(k);
+            find_non_finite(v, path)
+        })
+    } else if let Some(array) = value.read_lock::<Array>() {
+        array.iter().enumerate().find_map(|(i, v)| {
+            path.truncate(len);
+            path.push_str(&format!("[{i}]"));
+            find_non_finite(v, path)
+        })
+    } else {
+        None
+    };
+
+    path.truncate(len);
+    found
+}
+
+/// Return an error if a script result contains a non-finite floating-point number.
+fn check_non_finite(value: &Dynamic, fn_name: &str) -> RhaiResult<()> {
+    match find_non_finite(value, &mut String::new()) {
+        Some((path, f)) => Err(EvalAltResult::ErrorSystem(
+            format!("result of `{fn_name}` contains non-finite number `{f}` at `result{path}`"),
+            "non-finite number".into(),
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Convert all floating-point numbers with no fractional part within a [`Dynamic`] value
+/// into integers, recursively.
+fn integral_floats_to_int(value: &mut Dynamic) {
+    if let Ok(f) = value.as_float() {
+        if f.fract() == 0.0 && f >= INT::MIN as FLOAT && f < INT::MAX as FLOAT {
+            *value = Dynamic::from_int(f as INT);
+        }
+    } else if value.is_map() {
+        if let Some(mut map) = value.write_lock::<Map>() {
+            map.values_mut().for_each(integral_floats_to_int);
+        }
+    } else if value.is_array() {
+        if let Some(mut array) = value.write_lock::<Array>() {
+            array.iter_mut().for_each(integral_floats_to_int);
+        }
+    }
+}
+
+/// Sort the keys of all object maps within a JSON value, recursively.
+fn sort_map_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (_, v) in &mut entries {
+                sort_map_keys(v);
+            }
+            map.extend(entries);
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_map_keys),
+        _ => (),
+    }
+}
+
+/// Structured information on an error during script evaluation, suitable for highlighting the
+/// location of the error in an editor.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ScriptError {
+    /// Line number (1-based) of the error, if available.
+    pub line: Option<usize>,
+    /// Column number (1-based) of the error, if available.
+    pub column: Option<usize>,
+    /// Error message, without position information.
+    pub message: String,
+    /// Source of the script (usually the script file path) where the error occurred, if available.
+    pub source: Option<String>,
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref source) = self.source {
+            write!(f, "`{source}`: ")?;
+        }
+        f.write_str(&self.message)?;
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, " (line {line}, position {column})"),
+            (Some(line), None) => write!(f, " (line {line})"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl ScriptError {
+    /// Create a [`ScriptError`] from a [Rhai error][EvalAltResult].
+    ///
+    /// Errors nested within function calls and modules are unwrapped to the innermost error,
+    /// which is where execution actually failed. `source` is used as the script source if the
+    /// error does not carry one.
+    #[must_use]
+    pub fn new(err: &EvalAltResult, source: Option<&str>) -> Self {
+        let mut source = source;
+        let mut err = err;
+
+        loop {
+            match err {
+                EvalAltResult::ErrorInFunctionCall(_, src, e, _) => {
+                    if !src.is_empty() {
+                        source = Some(src.as_str());
+                    }
+                    err = e.as_ref();
+                }
+                EvalAltResult::ErrorInModule(_, e, _) => err = e.as_ref(),
+                _ => break,
+            }
+        }
+
+        let pos = err.position();
+        let message = match err {
+            EvalAltResult::ErrorRuntime(v, _) => v.to_string(),
+            _ => {
+                let message = err.to_string();
+                let suffix = format!(" ({pos})");
+                message
+                    .strip_suffix(&suffix)
+                    .map(ToString::to_string)
+                    .unwrap_or(message)
+            }
+        };
+
+        Self {
+            line: pos.line(),
+            column: pos.position(),
+            message,
+            source: source.map(ToString::to_string),
+        }
+    }
+}
+
+/// The last error of a script file, tracked if enabled via
+/// [`RhaiScriptBuilder::with_error_tracking`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LastError {
+    /// The error.
+    pub error: ScriptError,
+    /// Time when the error occurred.
+    pub timestamp: std::time::SystemTime,
+}
+
+/// Report of a [reload][RhaiScript::reload] of scripts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct ReloadReport {
+    /// Script files that were recompiled (new or changed).
+    pub recompiled: Vec<PathBuf>,
+    /// Script files that did not change.
+    pub unchanged: Vec<PathBuf>,
+    /// Script files that were removed.
+    pub removed: Vec<PathBuf>,
+    /// Script files that failed to compile, with the error messages.
+    ///
+    /// If not empty, the reload did not take effect and all other lists are empty.
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+/// Signature of a function defined in a script.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FnSignature {
+    /// Name of the function.
+    pub name: String,
+    /// Names of the function's parameters, in order.
+    pub params: Vec<String>,
+    /// Is the function [private][FnAccess::Private]?
+    pub private: bool,
+}
+
+impl From<&ScriptFnMetadata<'_>> for FnSignature {
+    fn from(f: &ScriptFnMetadata<'_>) -> Self {
+        Self {
+            name: f.name.to_string(),
+            params: f.params.iter().map(ToString::to_string).collect(),
+            private: f.access == FnAccess::Private,
+        }
+    }
+}
+
+/// Documentation of a function defined in a script.
+///
+/// Requires the `metadata` feature.
+#[cfg(feature = "metadata")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FnDoc {
+    /// Name of the function.
+    pub name: String,
+    /// Names of the function's parameters, in order.
+    pub params: Vec<String>,
+    /// Is the function [private][FnAccess::Private]?
+    pub private: bool,
+    /// Doc-comments of the function, one per line (or block), including the comment markers
+    /// (e.g. `///`).
+    pub comments: Vec<String>,
+}
+
+/// Builder for a [`RhaiScript`] instance.
+#[derive(Debug, Clone, Default)]
+pub struct RhaiScriptBuilder {
+    /// Additional directories containing Rhai scripts, searched in order after the main one.
+    additional_scripts_paths: Vec<PathBuf>,
+    /// Maximum size (in bytes) of a script file, if any.
+    max_file_size: Option<u64>,
+    /// Maximum serialized size (in bytes) of the data passed to a script, if any.
+    max_input_size: Option<u64>,
+    /// Maximum expression nesting depths (global level, function level), if any.
+    max_expr_depths: Option<(usize, usize)>,
+    /// Limits on the sizes of data created by scripts.
+    memory_limits: MemoryLimits,
+    /// Name of the function called by [`RhaiScript::run_script_default`], if not the default.
+    default_fn_name: Option<String>,
+    /// Register random number functions.
+    enable_rng: bool,
+    /// Fixed seed for the random number functions, if any.
+    rng_seed: Option<u64>,
+    /// Track the last error of each script file.
+    track_errors: bool,
+    /// Allow scripts directories to be missing.
+    optional: bool,
+    /// Path to the prelude script, if any.
+    prelude: Option<PathBuf>,
+    /// Custom operators (symbol, precedence).
+    custom_operators: Vec<(String, u8)>,
+    /// Custom syntax definitions.
+    custom_syntax: Vec<CustomSyntaxDef>,
+    /// Custom module resolver replacing the default file-based resolution, if any.
+    module_resolver: Option<SharedModuleResolver>,
+    /// Tera view engine for the `render` script function, if any.
+    #[cfg(feature = "render")]
+    tera_view: Option<TeraView>,
+    /// Variable resolver, if any.
+    var_resolver: Option<VarResolver>,
+    /// Read-only state shared by all scripts via the `shared` function.
+    shared_state: Dynamic,
+    /// Common setup shared with the filters engine, if any.
+    common_setup: Option<CommonSetup>,
+    /// Hook called on each compiled script before it is cached, if any.
+    on_compile: Option<CompileHook>,
+    /// Constants available to scripts at compile time.
+    constants: Scope<'static>,
+    /// Hook called on script compilation events, if any.
+    on_compile_event: Option<CompileEventHook>,
+    /// Function applied to every successful script result, if any.
+    finalizer: Option<Finalizer>,
+    /// Canonicalize script paths before caching?
+    canonicalize_paths: bool,
+    /// Hook called before every script call, if any.
+    before_call: Option<BeforeCallHook>,
+    /// Hook called after every script call, if any.
+    after_call: Option<AfterCallHook>,
+    /// Tera i18n function registered as `t`, if any.
+    i18n: Option<I18nFunction>,
+    /// Maximum number of concurrent script calls and what to do when it is reached, if any.
+    max_concurrency: Option<(usize, BusyPolicy)>,
+    /// Progress callback of the engine, if any.
+    on_progress: Option<ProgressHook>,
+}
+
+/// What to do when the maximum number of concurrent script calls (set via
+/// [`RhaiScriptBuilder::with_max_concurrency`]) is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BusyPolicy {
+    /// Wait until a running script call completes. This is the default.
+    #[default]
+    Queue,
+    /// Fail immediately with an error tagged [`SCRIPTING_BUSY`].
+    FailFast,
+}
+
+/// Limit on the number of concurrent script calls, set via
+/// [`RhaiScriptBuilder::with_max_concurrency`].
+#[derive(Debug)]
+struct ConcurrencyLimit {
+    /// Semaphore holding one permit per allowed concurrent call.
+    semaphore: Arc<tokio::sync::Semaphore>,
+    /// Maximum number of concurrent calls.
+    max: usize,
+    /// What to do when the limit is reached.
+    policy: BusyPolicy,
+}
+
+/// Tera i18n function, set via [`RhaiScriptBuilder::with_i18n`].
+#[derive(Clone)]
+struct I18nFunction(Arc<dyn tera::Function>);
+
+impl Debug for I18nFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("I18nFunction")
+    }
+}
+
+/// Event in loading a script, passed to the hook set via
+/// [`RhaiScriptBuilder::on_compile_event`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompileEvent {
+    /// The compiled script was served from the cache.
+    CacheHit,
+    /// The script was not in the cache and will be compiled.
+    CacheMiss,
+    /// The script was compiled successfully.
+    Compiled {
+        /// Time taken to compile the script.
+        duration: std::time::Duration,
+    },
+}
+
+/// Hook receiving [compile events][CompileEvent], set via [`RhaiScriptBuilder::on_compile_event`].
+#[derive(Clone)]
+struct CompileEventHook(Arc<dyn Fn(&Path, CompileEvent) + Send + Sync>);
+
+impl Debug for CompileEventHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CompileEventHook")
+    }
+}
+
+/// Function applied to script results, set via [`RhaiScriptBuilder::with_finalizer`].
+#[derive(Clone)]
+struct Finalizer(Arc<dyn Fn(Value) -> Value + Send + Sync>);
+
+impl Debug for Finalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Finalizer")
+    }
+}
+
+/// Progress callback of the [`Engine`], set via [`RhaiScriptBuilder::on_progress`].
+#[derive(Clone)]
+struct ProgressHook(Arc<dyn Fn(u64) -> Option<Dynamic> + Send + Sync>);
+
+impl Debug for ProgressHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
+
+/// Hook called before every script call, set via [`RhaiScriptBuilder::before_call`].
+#[derive(Clone)]
+struct BeforeCallHook(Arc<dyn Fn(&str, &str) + Send + Sync>);
+
+impl Debug for BeforeCallHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BeforeCallHook")
+    }
+}
+
+/// Hook called after every script call, set via [`RhaiScriptBuilder::after_call`].
+#[derive(Clone)]
+struct AfterCallHook(Arc<dyn Fn(&str, &str, &RhaiResult<Value>) + Send + Sync>);
+
+impl Debug for AfterCallHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AfterCallHook")
+    }
+}
+
+/// Hook validating a compiled script, set via [`RhaiScriptBuilder::on_compile`].
+#[derive(Clone)]
+struct CompileHook(Arc<dyn Fn(&AST) -> Result<()> + Send + Sync>);
+
+impl Debug for CompileHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CompileHook")
+    }
+}
+
+/// A [common setup][EngineSetup] set via [`RhaiScriptBuilder::with_common_setup`].
+#[derive(Clone)]
+struct CommonSetup(EngineSetup);
+
+impl Debug for CommonSetup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CommonSetup")
+    }
+}
+
+/// Callback resolving variables in scripts.
+type VarResolverFn =
+    Arc<dyn Fn(&str, usize, EvalContext) -> RhaiResult<Option<Dynamic>> + Send + Sync>;
+
+/// A variable resolver set via [`RhaiScriptBuilder::with_var_resolver`].
+#[derive(Clone)]
+struct VarResolver(VarResolverFn);
+
+impl Debug for VarResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VarResolver")
+    }
+}
+
+/// A shared [module resolver][ModuleResolver] set via
+/// [`RhaiScriptBuilder::with_module_resolver`].
+#[derive(Clone)]
+struct SharedModuleResolver(Arc<dyn ModuleResolver>);
+
+impl Debug for SharedModuleResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedModuleResolver")
+    }
+}
+
+impl ModuleResolver for SharedModuleResolver {
+    #[inline(always)]
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> std::result::Result<SharedModule, Box<EvalAltResult>> {
+        self.0.resolve(engine, source, path, pos)
+    }
+    #[inline(always)]
+    fn resolve_ast(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Option<std::result::Result<AST, Box<EvalAltResult>>> {
+        self.0.resolve_ast(engine, source, path, pos)
+    }
+}
+
+thread_local! {
+    /// Paths of the modules currently being imported on this thread, outermost first.
+    static IMPORTS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// [Module resolver][ModuleResolver] wrapping another resolver, detecting circular imports.
+///
+/// A module importing itself (directly or indirectly) fails with a
+/// `circular import detected: a -> b -> a` error instead of recursing until the call stack
+/// limit is reached.
+struct CycleDetectingResolver<R: ModuleResolver>(R);
+
+impl<R: ModuleResolver> CycleDetectingResolver<R> {
+    /// Resolve a module via the wrapped resolver, tracking the chain of imports.
+    fn track<T>(
+        path: &str,
+        resolve: impl FnOnce() -> std::result::Result<T, Box<EvalAltResult>>,
+    ) -> std::result::Result<T, Box<EvalAltResult>> {
+        let cycle = IMPORTS.with(|imports| {
+            let imports = imports.borrow();
+            imports.iter().position(|p| p == path).map(|start| {
+                let mut chain: Vec<_> = imports[start..].iter().map(String::as_str).collect();
+                chain.push(path);
+                chain.join(" -> ")
+            })
+        });
+
+        if let Some(chain) = cycle {
+            debug!(target: ROOT, chain = chain.as_str(), "circular import detected");
+            return Err(EvalAltResult::ErrorSystem(
+                "circular import detected".to_string(),
+                chain.into(),
+            )
+            .into());
+        }
+
+        let _guard = ImportGuard::new(path);
+        resolve()
+    }
+}
+
+/// Guard tracking a module being imported on this thread, for [`CycleDetectingResolver`].
+///
+/// The module is untracked when the guard is dropped, even if resolving it panics.
+struct ImportGuard;
+
+impl ImportGuard {
+    /// Track a module being imported until the guard is dropped.
+    fn new(path: &str) -> Self {
+        IMPORTS.with(|imports| imports.borrow_mut().push(path.to_string()));
+        Self
+    }
+}
+
+impl Drop for ImportGuard {
+    fn drop(&mut self) {
+        let _ = IMPORTS.with(|imports| imports.borrow_mut().pop());
+    }
+}
+
+impl<R: ModuleResolver> ModuleResolver for CycleDetectingResolver<R> {
+    #[inline(always)]
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> std::result::Result<SharedModule, Box<EvalAltResult>> {
+        Self::track(path, || self.0.resolve(engine, source, path, pos))
+    }
+    #[inline(always)]
+    fn resolve_ast(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Option<std::result::Result<AST, Box<EvalAltResult>>> {
+        self.0.resolve_ast(engine, source, path, pos)
+    }
+}
+
+/// Callback implementing a custom syntax.
+type CustomSyntaxFn = Arc<dyn Fn(&mut EvalContext, &[Expression]) -> RhaiResult + Send + Sync>;
+
+/// A custom syntax definition registered via [`RhaiScriptBuilder::with_custom_syntax`].
+#[derive(Clone)]
+struct CustomSyntaxDef {
+    /// Symbols making up the custom syntax.
+    symbols: Vec<String>,
+    /// Can the custom syntax change the scope?
+    scope_may_change: bool,
+    /// Callback implementing the custom syntax.
+    func: CustomSyntaxFn,
+}
+
+impl Debug for CustomSyntaxDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomSyntaxDef")
+            .field("symbols", &self.symbols)
+            .field("scope_may_change", &self.scope_may_change)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RhaiScriptBuilder {
+    /// Create a new [`RhaiScriptBuilder`] with default options.
+    #[inline(always)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a directory containing Rhai scripts, searched after the main scripts directory
+    /// (and any directories previously added).
+    ///
+    /// All scripts directories appear as one logical namespace, the first script file found wins.
+    /// [`import`](https://rhai.rs/book/ref/modules/import.html) statements also search all
+    /// scripts directories in the same order.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_scripts_path(mut self, scripts_path: impl Into<PathBuf>) -> Self {
+        self.additional_scripts_paths.push(scripts_path.into());
+        self
+    }
+
+    /// Set the maximum size (in bytes) of a script file.
+    ///
+    /// Script files larger than this fail with an error before they are read and compiled.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    /// Set the maximum serialized (JSON) size (in bytes) of the data passed to a script.
+    ///
+    /// Larger data fails with an error before it is converted for use in script.
+    /// [BLOBs][RhaiScript::run_script_with_blob] are limited by their length instead.
+    /// Default unlimited.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_max_input_size(mut self, max_input_size: u64) -> Self {
+        self.max_input_size = Some(max_input_size);
+        self
+    }
+
+    /// Set [limits][MemoryLimits] on the sizes of data (arrays, object maps and strings) created
+    /// by scripts.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let script = RhaiScript::builder()
+    ///     .with_memory_limits(MemoryLimits::strict().max_array_size(100_000))
+    ///     .build("assets/scripts")?;
+    /// ```
+    #[inline(always)]
+    #[must_use]
+    pub fn with_memory_limits(mut self, limits: MemoryLimits) -> Self {
+        self.memory_limits = limits;
+        self
+    }
+
+    /// Set the maximum expression nesting depths, at global level and within functions.
+    ///
+    /// See [`Engine::set_max_expr_depths`].
+    #[inline(always)]
+    #[must_use]
+    pub fn with_max_expr_depths(
+        mut self,
+        max_expr_depth: usize,
+        max_function_expr_depth: usize,
+    ) -> Self {
+        self.max_expr_depths = Some((max_expr_depth, max_function_expr_depth));
+        self
+    }
+
+    /// Set the name of the function called by [`RhaiScript::run_script_default`].
+    ///
+    /// Default is [`RhaiScript::DEFAULT_FN_NAME`].
+    #[inline(always)]
+    #[must_use]
+    pub fn with_default_fn_name(mut self, fn_name: impl Into<String>) -> Self {
+        self.default_fn_name = Some(fn_name.into());
+        self
+    }
+
+    /// Register random number functions `rand`, `rand_float` and `rand_range` for scripts,
+    /// seeded randomly.
+    ///
+    /// * `rand()` returns a random integer.
+    /// * `rand_float()` returns a random floating-point number between `0.0` (inclusive) and
+    ///   `1.0` (exclusive).
+    /// * `rand_range(start, end)` returns a random integer between `start` (inclusive) and
+    ///   `end` (exclusive), or `start` if the range is empty.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_rng(mut self) -> Self {
+        self.enable_rng = true;
+        self
+    }
+
+    /// Register random number functions (see [`with_rng`][RhaiScriptBuilder::with_rng]) for
+    /// scripts, with a fixed seed.
+    ///
+    /// Under a fixed seed, scripts produce the same sequence of random numbers on every run,
+    /// which is useful for tests.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_seeded_rng(mut self, seed: u64) -> Self {
+        self.enable_rng = true;
+        self.rng_seed = Some(seed);
+        self
+    }
+
+    /// Track the last error of each script file, retrievable via [`RhaiScript::last_errors`].
+    ///
+    /// Error tracking is disabled by default to avoid overhead when unused.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_error_tracking(mut self) -> Self {
+        self.track_errors = true;
+        self
+    }
+
+    /// Allow scripts directories to be missing.
+    ///
+    /// Scripting is effectively disabled for missing scripts directories:
+    /// [`run_script_if_exists`][RhaiScript::run_script_if_exists] always returns `Value::Null`,
+    /// and [`run_script`][RhaiScript::run_script] always returns a script-not-found error
+    /// (unless the script is registered via [`register_source`][RhaiScript::register_source]).
+    ///
+    /// This is useful when scripting is an optional extension point.
+    #[inline(always)]
+    #[must_use]
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// Set a prelude script whose functions are available in all scripts without `import`.
+    ///
+    /// The prelude script is compiled once when the [`RhaiScript`] instance is built, and its
+    /// functions merged into every script when it is compiled. Functions in a script override
+    /// prelude functions with the same name and number of parameters.
+    ///
+    /// Only functions are merged; top-level statements in the prelude script are ignored.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_prelude(mut self, prelude_path: impl Into<PathBuf>) -> Self {
+        self.prelude = Some(prelude_path.into());
+        self
+    }
+    /// Register a custom operator with a precedence.
+    ///
+    /// The precedence must be greater than zero; higher values bind tighter. For reference,
+    /// see the [precedence of built-in operators](https://rhai.rs/book/engine/custom-op.html).
+    ///
+    /// The function implementing the operator (with the same name as the operator symbol and
+    /// taking two parameters) must be registered separately, e.g. via
+    /// [`build_with_setup`][RhaiScriptBuilder::build_with_setup].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let script = RhaiScript::builder()
+    ///     .with_custom_operator("~>", 160)
+    ///     .build_with_setup("assets/scripts", |engine| {
+    ///         engine.register_fn("~>", |x: INT, y: INT| x * 10 + y);
+    ///     })?;
+    /// ```
+    #[inline(always)]
+    #[must_use]
+    pub fn with_custom_operator(mut self, symbol: impl Into<String>, precedence: u8) -> Self {
+        self.custom_operators.push((symbol.into(), precedence));
+        self
+    }
+    /// Set a custom [module resolver][ModuleResolver] for `import` statements in scripts.
+    ///
+    /// This _replaces_ the default resolution of modules from files under the scripts
+    /// directories. Use a [`ModuleResolversCollection`] to combine a custom resolver with a
+    /// [`FileModuleResolver`] if file-based modules are still needed.
+    ///
+    /// Script files run via [`RhaiScript`] are still loaded from the scripts directories.
+    ///
+    /// Circular imports are detected for custom resolvers as well.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_module_resolver(mut self, resolver: impl ModuleResolver + 'static) -> Self {
+        self.module_resolver = Some(SharedModuleResolver(Arc::new(resolver)));
+        self
+    }
+    /// Register a `render(template_name, context)` function into the Rhai [`Engine`] that
+    /// renders a Tera template (with an object map as the context) into a string.
+    ///
+    /// Requires the `render` feature.
+    ///
+    /// The [`TeraView`] is captured when the [`RhaiScript`] instance is built and lives as long
+    /// as the global Rhai [`Engine`] (i.e. for the lifetime of the application). Filters or
+    /// templates added to the application's view engine afterwards may not be visible to scripts,
+    /// so pass the view after it has been fully set up.
+    #[cfg(feature = "render")]
+    #[inline(always)]
+    #[must_use]
+    pub fn with_tera_view(mut self, view: TeraView) -> Self {
+        self.tera_view = Some(view);
+        self
+    }
+    /// Set a variable resolver, called to resolve variables in scripts.
+    ///
+    /// See [`Engine::on_var`] for details.
+    ///
+    /// The resolver is called with the variable name, its index in the [`Scope`] (counting from
+    /// the end, zero if not found) and the evaluation context, _before_ the [`Scope`] is searched.
+    /// Returning `Ok(None)` falls back to normal lookup in the [`Scope`]; returning a value
+    /// shadows any variable of the same name in the [`Scope`]. Resolved values are constants and
+    /// cannot be assigned to.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let script = RhaiScript::builder()
+    ///     .with_var_resolver(move |name, _, _| match name.strip_prefix("config_") {
+    ///         Some(key) => Ok(settings.get(key).map(|v| to_dynamic(v)).transpose()?),
+    ///         None => Ok(None),
+    ///     })
+    ///     .build("assets/scripts")?;
+    /// ```
+    #[inline(always)]
+    #[must_use]
+    pub fn with_var_resolver(
+        mut self,
+        resolver: impl Fn(&str, usize, EvalContext) -> RhaiResult<Option<Dynamic>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.var_resolver = Some(VarResolver(Arc::new(resolver)));
+        self
+    }
+    /// Set read-only state shared by all scripts, returned by the `shared()` script function.
+    ///
+    /// The state is stored once and not serialized per call, so it is suitable for large
+    /// read-only context (e.g. configuration).
+    ///
+    /// The state is held as a read-only [shared value][Dynamic::into_shared], so calling
+    /// `shared()` does not copy it, nor does reading from it directly (e.g.
+    /// `shared().config.name`). Scripts cannot modify the state, and modifying a copy of it
+    /// (e.g. assigned to a variable) has no effect on other scripts.
+    ///
+    /// Use [`RhaiScript::update_shared_state`] to replace it. Default `()`.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_shared_state(mut self, state: impl Into<Dynamic>) -> Self {
+        self.shared_state = state.into();
+        self
+    }
+    /// Set the [common setup][EngineSetup] of the scripts engine shared with the filters engine.
+    ///
+    /// It is run before the custom setup passed to
+    /// [`build_with_setup`][RhaiScriptBuilder::build_with_setup].
+    #[inline(always)]
+    #[must_use]
+    pub fn with_common_setup(mut self, setup: EngineSetup) -> Self {
+        self.common_setup = Some(CommonSetup(setup));
+        self
+    }
+    /// Set a hook called on each compiled script before it is cached.
+    ///
+    /// The hook can inspect the [`AST`] (e.g. to forbid certain function calls or enforce naming
+    /// rules) and reject the script by returning an error, which should name the violated rule.
+    /// A rejected script is not cached and fails to run with that error.
+    ///
+    /// The hook is not called for the prelude script.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let script = RhaiScript::builder()
+    ///     .on_compile(|ast| {
+    ///         match ast.iter_functions().find(|f| f.name.starts_with('_')) {
+    ///             Some(f) => Err(Error::string(&format!("rule `no-underscore`: `{}`", f.name))),
+    ///             None => Ok(()),
+    ///         }
+    ///     })
+    ///     .build("assets/scripts")?;
+    /// ```
+    #[inline(always)]
+    #[must_use]
+    pub fn on_compile(
+        mut self,
+        hook: impl Fn(&AST) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.on_compile = Some(CompileHook(Arc::new(hook)));
+        self
+    }
+    /// Define a constant available to all scripts, e.g. a feature flag such as `DEBUG`.
+    ///
+    /// Constants are known when scripts are compiled, so with optimizations turned on (the
+    /// default) they are propagated into the scripts and code depending on them (e.g.
+    /// `if DEBUG { ... }`) is resolved at compile time.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let script = RhaiScript::builder()
+    ///     .with_compile_constant("DEBUG", cfg!(debug_assertions))
+    ///     .build("assets/scripts")?;
+    /// ```
+    #[inline(always)]
+    #[must_use]
+    pub fn with_compile_constant(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<Dynamic>,
+    ) -> Self {
+        self.constants.push_constant_dynamic(name.into(), value.into());
+        self
+    }
+    /// Define constants available to all scripts from a [`Scope`], for optimization at compile
+    /// time (same as [`with_compile_constant`][RhaiScriptBuilder::with_compile_constant] for
+    /// each variable in the scope).
+    ///
+    /// All variables in the scope are defined as constants, whether or not they are constant in
+    /// the scope.
+    ///
+    /// Constants are folded into scripts when they are compiled, so changing them requires
+    /// recompiling the scripts, i.e. building a new engine.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_optimization_constants(mut self, constants: Scope<'static>) -> Self {
+        for (name, _, value) in constants.iter() {
+            self.constants.push_constant_dynamic(name.to_string(), value);
+        }
+        self
+    }
+    /// Set a hook receiving [events][CompileEvent] when a script is loaded for running: whether
+    /// it is served from the cache or compiled (and how long compilation took).
+    ///
+    /// The hook is called with the path of the script file. Keep it cheap, as it is called on
+    /// every script run.
+    #[inline(always)]
+    #[must_use]
+    pub fn on_compile_event(
+        mut self,
+        hook: impl Fn(&Path, CompileEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_compile_event = Some(CompileEventHook(Arc::new(hook)));
+        self
+    }
+    /// Set a function applied to the result of every successful script run, e.g. to wrap it in
+    /// a response envelope such as `{ "data": ... }`.
+    ///
+    /// The finalizer is applied to the result of every method returning JSON, including
+    /// [`run_script`][RhaiScript::run_script], [`eval_script`][RhaiScript::eval_script],
+    /// [`run_hook`][RhaiScript::run_hook], [`run_script_many`][RhaiScript::run_script_many] and
+    /// the methods turning the result into a response or writing it into a writer (for
+    /// redirects via [`run_script_as_response`][RhaiScript::run_script_as_response], only to
+    /// a result that is not a redirect). It is applied after the result is converted into JSON
+    /// (and after keys are sorted, if requested). It is not applied if the script fails, so
+    /// errors are never wrapped.
+    ///
+    /// To skip the finalizer, use [`run_script_dynamic`][RhaiScript::run_script_dynamic], which
+    /// returns the raw result (as do the methods built on it, such as
+    /// [`run_script_as`][RhaiScript::run_script_as]).
+    #[inline(always)]
+    #[must_use]
+    pub fn with_finalizer(
+        mut self,
+        finalizer: impl Fn(Value) -> Value + Send + Sync + 'static,
+    ) -> Self {
+        self.finalizer = Some(Finalizer(Arc::new(finalizer)));
+        self
+    }
+    /// Canonicalize the paths of script files (via [`std::fs::canonicalize`]) before caching,
+    /// so that the same physical file reached via different paths (e.g. symlinks) is compiled
+    /// and cached only once.
+    ///
+    /// This costs a file system call on every script run, even when the script is cached.
+    #[inline(always)]
+    #[must_use]
+    pub fn canonicalize_paths(mut self) -> Self {
+        self.canonicalize_paths = true;
+        self
+    }
+    /// Set a progress callback for the Rhai [`Engine`], called with the number of operations
+    /// performed so far (see [`Engine::on_progress`]).
+    ///
+    /// Return `Some(token)` to terminate the script with the token, or `None` to continue.
+    ///
+    /// Use this instead of calling [`Engine::on_progress`] in a custom engine setup, which
+    /// would replace the callback enforcing [per-call limits][RunOptions::max_operations]. The
+    /// callback runs after per-call limits are checked.
+    #[inline(always)]
+    #[must_use]
+    pub fn on_progress(
+        mut self,
+        callback: impl Fn(u64) -> Option<Dynamic> + Send + Sync + 'static,
+    ) -> Self {
+        self.on_progress = Some(ProgressHook(Arc::new(callback)));
+        self
+    }
+    /// Set a hook called before every script call (e.g. for audit logging), with the script file
+    /// and the function name.
+    ///
+    /// The hook is called for every script function call made via a [`RhaiScript`] method
+    /// (e.g. [`run_script`][RhaiScript::run_script], [`eval_script`][RhaiScript::eval_script],
+    /// [`run_hook`][RhaiScript::run_hook] or [`run_script_many`][RhaiScript::run_script_many]),
+    /// once the script is compiled. It is not called if the script file does not exist or fails
+    /// to compile, nor for Tera filters.
+    #[inline(always)]
+    #[must_use]
+    pub fn before_call(mut self, hook: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        self.before_call = Some(BeforeCallHook(Arc::new(hook)));
+        self
+    }
+    /// Set a hook called after every script call (e.g. for audit logging), with the script file,
+    /// the function name and the result.
+    ///
+    /// The hook is called in the same cases as the hook set via
+    /// [`before_call`][RhaiScriptBuilder::before_call], whether the call succeeds or fails.
+    ///
+    /// The result is that of the script function itself, converted into JSON, before any further
+    /// processing by the calling method (e.g. by the
+    /// [finalizer][RhaiScriptBuilder::with_finalizer], or converting `this` back into the type of
+    /// `data`). Results that cannot be converted into JSON (e.g. custom types returned to
+    /// [`run_script_dynamic`][RhaiScript::run_script_dynamic]) are passed as `null`.
+    #[inline(always)]
+    #[must_use]
+    pub fn after_call(
+        mut self,
+        hook: impl Fn(&str, &str, &RhaiResult<Value>) + Send + Sync + 'static,
+    ) -> Self {
+        self.after_call = Some(AfterCallHook(Arc::new(hook)));
+        self
+    }
+    /// Register the Tera i18n function as the `t` function available to all scripts (not only
+    /// Tera filters), e.g. for localizing error messages.
+    ///
+    /// `t` can be called with an object map of arguments (e.g.
+    /// `t(#{ key: "hello", lang: "fr" })`) or with a key and a language (e.g. `t("hello", "fr")`),
+    /// same as in Tera filters.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_i18n(mut self, i18n: impl tera::Function + 'static) -> Self {
+        self.i18n = Some(I18nFunction(Arc::new(i18n)));
+        self
+    }
+    /// Limit the number of script calls running concurrently via
+    /// [`run_script_async`][RhaiScript::run_script_async] and
+    /// [`run_script_blocking`][RhaiScript::run_script_blocking], providing backpressure under
+    /// load. Over-limit calls either wait or fail, according to the [policy][BusyPolicy].
+    ///
+    /// Other methods (e.g. [`run_script`][RhaiScript::run_script]) are not limited.
+    ///
+    /// `max` must be at least 1, otherwise [`build`][RhaiScriptBuilder::build] fails (with a
+    /// maximum of zero, every call would wait forever or fail).
+    #[inline(always)]
+    #[must_use]
+    pub fn with_max_concurrency(mut self, max: usize, policy: BusyPolicy) -> Self {
+        self.max_concurrency = Some((max, policy));
+        self
+    }
+    /// Register a custom syntax.
+    ///
+    /// See [`Engine::register_custom_syntax`] for details.
+    #[inline(always)]
+    #[must_use]
+    pub fn with_custom_syntax(
+        mut self,
+        symbols: impl IntoIterator<Item = impl Into<String>>,
+        scope_may_change: bool,
+        func: impl Fn(&mut EvalContext, &[Expression]) -> RhaiResult + Send + Sync + 'static,
+    ) -> Self {
+        self.custom_syntax.push(CustomSyntaxDef {
+            symbols: symbols.into_iter().map(Into::into).collect(),
+            scope_may_change,
+            func: Arc::new(func),
+        });
+        self
+    }
+
+    /// Build the [`RhaiScript`] instance.
+    ///
+    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    ///
+    /// # Errors
+    ///
+    /// * Error if any scripts directory does not exist.
+    /// * Error if any scripts path is not a directory.
+    /// * Error if there is a syntax error in the prelude script.
+    /// * Error if a custom operator or custom syntax cannot be registered.
+    /// * Error if the [maximum number of concurrent script calls][RhaiScriptBuilder::with_max_concurrency]
+    ///   is zero.
+    #[inline(always)]
+    pub fn build(self, scripts_path: impl Into<PathBuf>) -> Result<RhaiScript> {
+        self.build_with_setup(scripts_path, |_| {})
+    }
+
+    /// Build the [`RhaiScript`] instance with custom setup.
+    ///
+    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    ///
+    /// The custom setup must not call [`Engine::on_progress`], which would replace the callback
+    /// enforcing [per-call limits][RunOptions::max_operations]. Use
+    /// [`on_progress`][RhaiScriptBuilder::on_progress] instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    ///
+    /// # Errors
+    ///
+    /// * Error if any scripts directory does not exist.
+    /// * Error if any scripts path is not a directory.
+    /// * Error if there is a syntax error in the prelude script.
+    /// * Error if a custom operator or custom syntax cannot be registered.
+    /// * Error if the [maximum number of concurrent script calls][RhaiScriptBuilder::with_max_concurrency]
+    ///   is zero.
+    pub fn build_with_setup(
+        self,
+        scripts_path: impl Into<PathBuf>,
+        setup: impl FnOnce(&mut Engine),
+    ) -> Result<RhaiScript> {
+        if matches!(self.max_concurrency, Some((0, _))) {
+            return Err(Error::string(
+                "maximum number of concurrent script calls must be at least 1",
+            ));
+        }
+
+        let mut scripts_paths = vec![scripts_path.into()];
+        scripts_paths.extend(self.additional_scripts_paths);
+
+        if let Some(path) = scripts_paths.iter().find(|path| path.exists() && !path.is_dir()) {
+            return Err(Error::string(&format!(
+                "scripts path is not a directory: `{}`",
+                path.to_string_lossy()
+            )));
+        }
+
+        for path in scripts_paths.iter().filter(|path| !path.exists()) {
+            if !self.optional {
+                return Err(Error::string(&format!(
+                    "missing scripts directory: `{}`",
+                    path.to_string_lossy()
+                )));
+            }
+            info!(target: ROOT, dir = ?path, "scripts directory missing, scripting disabled for it");
+        }
+
+        let mut engine = Engine::new();
+
+        if let Some(resolver) = self.module_resolver {
+            engine.set_module_resolver(CycleDetectingResolver(resolver));
+        } else {
+            let mut resolver = ModuleResolversCollection::new();
+            for path in &scripts_paths {
+                let mut file_resolver = FileModuleResolver::new_with_path(path);
+                file_resolver.enable_cache(false);
+                resolver.push(file_resolver);
+            }
+            engine.set_module_resolver(CycleDetectingResolver(resolver));
+        }
+
+        let output = Arc::new(OutputHandlers::default());
+        let shared_state = Arc::new(RwLock::new(into_shared_state(self.shared_state)));
+
+        register_defaults(&mut engine, &output, &shared_state, self.on_progress);
+
+        if let Some((max_expr_depth, max_function_expr_depth)) = self.max_expr_depths {
+            engine.set_max_expr_depths(max_expr_depth, max_function_expr_depth);
+        }
+
+        if let Some(size) = self.memory_limits.max_array_size {
+            engine.set_max_array_size(size);
+        }
+        if let Some(size) = self.memory_limits.max_map_size {
+            engine.set_max_map_size(size);
+        }
+        if let Some(size) = self.memory_limits.max_string_size {
+            engine.set_max_string_size(size);
+        }
+
+        if self.enable_rng {
+            register_rng(&mut engine, self.rng_seed);
+        }
+
+        if let Some(I18nFunction(i18n)) = self.i18n {
+            register_i18n(&mut engine, i18n);
+        }
+
+        #[cfg(feature = "render")]
+        if let Some(view) = self.tera_view {
+            engine.register_fn("render", move |name: &str, context: Map| -> RhaiResult<String> {
+                let context: Value = from_dynamic(&context.into())?;
+                view.render(name, context)
+                    .map_err(|err| format!("cannot render `{name}`: {err}").into())
+            });
+        }
+
+        if let Some(VarResolver(resolver)) = self.var_resolver {
+            engine.on_var(move |name, index, context| resolver(name, index, context));
+        }
+
+        for (symbol, precedence) in &self.custom_operators {
+            engine
+                .register_custom_operator(symbol, *precedence)
+                .map_err(|err| Error::string(&format!("custom operator `{symbol}`: {err}")))?;
+        }
+
+        for syntax in self.custom_syntax {
+            let func = syntax.func;
+            engine
+                .register_custom_syntax(
+                    &syntax.symbols,
+                    syntax.scope_may_change,
+                    move |context, inputs| func(context, inputs),
+                )
+                .map_err(|err| {
+                    Error::string(&format!("custom syntax `{}`: {err}", syntax.symbols.join(" ")))
+                })?;
+        }
+
+        if let Some(CommonSetup(ref common_setup)) = self.common_setup {
+            common_setup(&mut engine);
+        }
+        setup(&mut engine);
+
+        let prelude = self
+            .prelude
+            .map(|path| {
+                engine
+                    .compile_file_with_scope(&self.constants, path.clone())
+                    .map(|ast| Arc::new(ast.clone_functions_only()))
+                    .map_err(|err| {
+                        Error::string(&format!("prelude `{}`: {err}", path.to_string_lossy()))
+                    })
+            })
+            .transpose()?;
+
+        ENGINE
+            .set(engine)
+            .expect("`RhaiScript::new` or `RhaiScript::new_with_setup` can be called only once.");
+
+        RHAI_SCRIPT
+            .set(RhaiScript {
+                scripts_paths: Arc::new(scripts_paths),
+                cache: Arc::new(RwLock::new(HashMap::new())),
+                modified: Arc::new(RwLock::new(HashMap::new())),
+                output,
+                max_file_size: self.max_file_size,
+                max_input_size: self.max_input_size,
+                shared_state,
+                on_compile: self.on_compile,
+                constants: Arc::new(self.constants),
+                enabled: Arc::new(AtomicBool::new(true)),
+                on_compile_event: self.on_compile_event,
+                finalizer: self.finalizer,
+                canonicalize_paths: self.canonicalize_paths,
+                before_call: self.before_call,
+                after_call: self.after_call,
+                concurrency: self.max_concurrency.map(|(max, policy)| {
+                    Arc::new(ConcurrencyLimit {
+                        semaphore: Arc::new(tokio::sync::Semaphore::new(max)),
+                        max,
+                        policy,
+                    })
+                }),
+                in_flight: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+                default_fn_name: self
+                    .default_fn_name
+                    .map_or_else(|| RhaiScript::DEFAULT_FN_NAME.into(), Into::into),
+                last_errors: self
+                    .track_errors
+                    .then(|| Arc::new(RwLock::new(HashMap::new()))),
+                prelude,
+            })
+            .unwrap();
+
+        Ok(RhaiScript::get_instance())
+    }
+}
+
+/// Information on a Tera filter registered from a Rhai script.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FilterInfo {
+    /// Name of the filter.
+    pub name: String,
+    /// Path to the script file defining the filter.
+    pub file: PathBuf,
+}
+
+/// Common setup of a Rhai [`Engine`], which can be shared between the scripts engine (via
+/// [`RhaiScriptBuilder::with_common_setup`]) and the filters engine (via
+/// [`FilterOptions::common_setup`]).
+///
+/// # Example
+///
+/// ```ignore
+/// let common: EngineSetup = Arc::new(|engine| {
+///     engine.register_fn("slugify", |s: &str| s.to_lowercase().replace(' ', "-"));
+/// });
+///
+/// let script = RhaiScript::builder()
+///     .with_common_setup(common.clone())
+///     .build("assets/scripts")?;
+///
+/// RhaiScript::register_tera_filters_with_options(
+///     &mut tera_engine,
+///     "assets/scripts/tera/filters",
+///     |_| {},
+///     i18n,
+///     FilterOptions::new().common_setup(common),
+/// )?;
+/// ```
+///
+/// The two engines still differ in other ways:
+///
+/// * The i18n function `t` is available in the filters engine whenever it is provided, but in
+///   the scripts engine only if registered via [`RhaiScriptBuilder::with_i18n`] (or
+///   [`with_i18n`][ScriptingEngineInitializerWithSetup::with_i18n] on the initializers).
+/// * The `fail`, `shared`, random number and data format functions, custom operators, custom
+///   syntax, the variable resolver, and resolution of modules under the scripts directories are
+///   only available in the scripts engine.
+/// * `print` and `debug` output can only be redirected in the scripts engine.
+pub type EngineSetup = Arc<dyn Fn(&mut Engine) + Send + Sync>;
+
+/// Predicate deciding whether a script function is registered as a Tera filter.
+pub type FilterPredicate = Arc<dyn Fn(&ScriptFnMetadata<'_>) -> bool + Send + Sync>;
+
+/// Options for registering Tera filters via [`RhaiScript::register_tera_filters_with_options`].
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct FilterOptions {
+    /// Maximum size (in bytes) of a filter script file, if any. Default `None`.
+    pub max_file_size: Option<u64>,
+    /// Predicate deciding whether a (public, single-parameter) script function is registered
+    /// as a Tera filter, if any. Default `None`, which registers all of them.
+    pub predicate: Option<FilterPredicate>,
+    /// Common setup of the filters engine shared with the scripts engine, if any.
+    /// Default `None`.
+    ///
+    /// It is run before the `engine_setup` closure passed to
+    /// [`RhaiScript::register_tera_filters_with_options`].
+    pub common_setup: Option<EngineSetup>,
+    /// What to do when filter scripts define filters with the same name.
+    /// Default [`FilterCollision::LastWins`].
+    pub collision: FilterCollision,
+}
+
+/// Policy for when multiple filter scripts define a Tera filter with the same name.
+///
+/// Filter scripts are processed in order of their paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FilterCollision {
+    /// Return an error naming the filter and the script files defining it.
+    ErrorOnCollision,
+    /// Keep the filter from the first script file.
+    FirstWins,
+    /// Keep the filter from the last script file. This is the default.
+    #[default]
+    LastWins,
+    /// Register each colliding filter under a name prefixed by the name of its script file
+    /// (without extension), e.g. `dates_format` for filter `format` in `dates.rhai`.
+    Prefix,
+}
+
+impl Debug for FilterOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FilterOptions")
+            .field("max_file_size", &self.max_file_size)
+            .field("predicate", &self.predicate.is_some())
+            .field("common_setup", &self.common_setup.is_some())
+            .field("collision", &self.collision)
+            .finish()
+    }
+}
+
+impl FilterOptions {
+    /// Create a new [`FilterOptions`] with default values.
+    #[inline(always)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the maximum size (in bytes) of a filter script file.
+    #[inline(always)]
+    #[must_use]
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+    /// Set a predicate deciding whether a script function is registered as a Tera filter.
+    ///
+    /// Only public functions taking one parameter are considered.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Register only functions named `email_*`
+    /// let options = FilterOptions::new().predicate(|f| f.name.starts_with("email_"));
+    /// ```
+    #[inline(always)]
+    #[must_use]
+    pub fn predicate(
+        mut self,
+        predicate: impl Fn(&ScriptFnMetadata<'_>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+    /// Set the [common setup][EngineSetup] of the filters engine shared with the scripts engine.
+    #[inline(always)]
+    #[must_use]
+    pub fn common_setup(mut self, setup: EngineSetup) -> Self {
+        self.common_setup = Some(setup);
+        self
+    }
+    /// Set the policy for when filter scripts define filters with the same name.
+    #[inline(always)]
+    #[must_use]
+    pub fn collision(mut self, policy: FilterCollision) -> Self {
+        self.collision = policy;
+        self
+    }
+}
+
+/// Tera filters compiled from Rhai scripts via [`RhaiScript::compile_tera_filters`].
+#[derive(Debug, Clone)]
+pub struct CompiledFilters {
+    /// Rhai [`Engine`] for running the filters.
+    engine: &'static Engine,
+    /// Filters with the names of the script functions and the compiled scripts defining them.
+    filters: Vec<(FilterInfo, String, Arc<AST>)>,
+}
+
+impl CompiledFilters {
+    /// Get information on all the compiled Tera filters.
+    pub fn filters(&self) -> impl Iterator<Item = &FilterInfo> {
+        self.filters.iter().map(|(info, ..)| info)
+    }
+    /// Register the compiled filters into a Tera instance.
+    ///
+    /// This can be called on multiple Tera instances. The scripts are not recompiled.
+    ///
+    /// Returns information on all the Tera filters registered.
+    pub fn apply_to(&self, tera: &mut TeraView) -> Vec<FilterInfo> {
+        let engine = self.engine;
+
+        for (info, fn_name, ast) in &self.filters {
+            let fn_name = fn_name.clone();
+            let ast = ast.clone();
+
+            let f = move |value: &Value, variables: &HashMap<String, Value>| -> tera::Result<Value> {
+                trace!(target: ROOT, fn_name, ?value, ?variables, "Rhai: call Tera filter");
+
+                let mut obj = to_dynamic(value).unwrap();
+                let dict = to_dynamic(variables).unwrap().cast::<Map>();
+
+                let scope = &mut Scope::new();
+                dict.iter().for_each(|(k, v)| {
+                    scope.push_dynamic(k.clone(), v.clone());
+                });
+
+                let options = CallFnOptions::new().bind_this_ptr(&mut obj);
+                let value = engine
+                    .call_fn_with_options::<Dynamic>(options, scope, &ast, &fn_name, (dict,))
+                    .map_err(tera::Error::msg)?;
+
+                let value = from_dynamic(&value).unwrap();
+                trace!(target: ROOT, ?value, fn_name, ?variables, "Rhai: return value from Tera filter");
+
+                Ok(value)
+            };
+
+            #[cfg(debug_assertions)]
+            let tera_engine = &mut *tera.tera.lock().expect("lock");
+
+            #[cfg(not(debug_assertions))]
+            let tera_engine = &mut tera.tera;
+
+            tera_engine.register_filter(&info.name, f);
+
+            info!(target: ROOT, fn_name = info.name.as_str(), file = ?info.file, "register Tera filter");
+        }
+
+        self.filters.iter().map(|(info, ..)| info.clone()).collect()
+    }
+}
+
+/// Resolve Tera filters with the same name according to a [collision policy][FilterCollision].
+fn resolve_filter_collisions(
+    mut filters: Vec<(FilterInfo, String, Arc<AST>)>,
+    policy: FilterCollision,
+) -> Result<Vec<(FilterInfo, String, Arc<AST>)>> {
+    let mut files: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for (info, ..) in &filters {
+        files.entry(info.name.clone()).or_default().push(info.file.clone());
+    }
+
+    let mut collisions: Vec<_> = files.iter().filter(|(_, files)| files.len() > 1).collect();
+    if collisions.is_empty() {
+        return Ok(filters);
+    }
+    collisions.sort();
+
+    for (name, files) in &collisions {
+        debug!(target: ROOT, filter = name.as_str(), ?files, ?policy, "Tera filter collision");
+    }
+
+    match policy {
+        FilterCollision::ErrorOnCollision => {
+            let (name, files) = collisions[0];
+            let files: Vec<_> = files
+                .iter()
+                .map(|file| format!("`{}`", file.to_string_lossy()))
+                .collect();
+            Err(Error::string(&format!(
+                "Tera filter `{name}` defined in multiple scripts: {}",
+                files.join(", ")
+            )))
+        }
+        FilterCollision::FirstWins => {
+            let mut seen = std::collections::HashSet::new();
+            filters.retain(|(info, ..)| seen.insert(info.name.clone()));
+            Ok(filters)
+        }
+        FilterCollision::LastWins => {
+            let mut seen = std::collections::HashSet::new();
+            filters.reverse();
+            filters.retain(|(info, ..)| seen.insert(info.name.clone()));
+            filters.reverse();
+            Ok(filters)
+        }
+        FilterCollision::Prefix => {
+            for (info, ..) in &mut filters {
+                if files[&info.name].len() > 1 {
+                    let stem = info.file.file_stem().unwrap_or_default().to_string_lossy();
+                    info.name = format!("{stem}_{}", info.name);
+                }
+            }
+            Ok(filters)
+        }
+    }
+}
+
+/// Resolve the Tera filters recompiled from a script file against all the filters registered,
+/// so that only the filters already registered from the script file are replaced.
+///
+/// See [`RhaiScript::reload_tera_filter`].
+fn resolve_reloaded_filters(
+    filters: Vec<(FilterInfo, String, Arc<AST>)>,
+    script: &Path,
+    policy: FilterCollision,
+    registered: &[FilterInfo],
+) -> Result<Vec<(FilterInfo, String, Arc<AST>)>> {
+    let stem = script.file_stem().unwrap_or_default().to_string_lossy();
+    let owner = |name: &str| registered.iter().find(|info| info.name == name);
+    let mut resolved = Vec::new();
+
+    for (mut info, fn_name, ast) in filters {
+        let prefixed = format!("{stem}_{}", info.name);
+
+        match owner(&info.name) {
+            // Already registered from this file
+            Some(other) if other.file == script => (),
+            _ if owner(&prefixed).map_or(false, |other| other.file == script) => {
+                info.name = prefixed;
+            }
+            // New filter
+            None => (),
+            // New filter whose name is taken by another file
+            Some(other) => match policy {
+                FilterCollision::ErrorOnCollision => {
+                    return Err(Error::string(&format!(
+                        "Tera filter `{}` defined in multiple scripts: `{}`, `{}`",
+                        info.name,
+                        other.file.to_string_lossy(),
+                        script.to_string_lossy()
+                    )));
+                }
+                FilterCollision::FirstWins | FilterCollision::LastWins => {
+                    debug!(target: ROOT, filter = info.name.as_str(), file = ?other.file, "Tera filter owned by another file");
+                    continue;
+                }
+                FilterCollision::Prefix => info.name = prefixed,
+            },
+        }
+
+        resolved.push((info, fn_name, ast));
+    }
+
+    Ok(resolved)
+}
+
+/// Convert the (possibly mutated) `this` back into the type of `data`.
+///
+/// On failure, the error names the offending field, if any.
+fn from_this<T: DeserializeOwned>(obj: &Dynamic, fn_name: &str) -> RhaiResult<T> {
+    from_dynamic(obj).or_else(|err| {
+        let value = from_dynamic::<Value>(obj).ok();
+
+        // JSON is more lenient with numbers (e.g. an integer for a floating-point field)
+        if let Some(Ok(obj)) = value.clone().map(serde_json::from_value::<T>) {
+            return Ok(obj);
+        }
+
+        // Re-run the conversion with path tracking to find the offending field
+        let path = value.and_then(|value| {
+            serde_path_to_error::deserialize::<_, T>(value)
+                .err()
+                .map(|e| e.path().to_string())
+        });
+
+        let message = match path {
+            Some(path) if path != "." => {
+                format!("invalid `this.{path}` after calling `{fn_name}`")
+            }
+            _ => format!("invalid `this` after calling `{fn_name}`"),
+        };
+
+        Err(EvalAltResult::ErrorSystem(message, err.into()).into())
+    })
+}
+
+/// Write the (possibly mutated) `this` back into `data` after a successful call, according to
+/// the [`ThisTypeMismatch`] policy in the [options][RunOptions].
+///
+/// `this_type` is the type name of `this` before the call.
+fn write_back_this<T: DeserializeOwned>(
+    options: &RunOptions,
+    data: &mut T,
+    obj: &Dynamic,
+    this_type: &str,
+    fn_name: &str,
+    source: Option<&str>,
+) -> RhaiResult<()> {
+    let type_changed = obj.type_name() != this_type;
+
+    match options.this_type_mismatch {
+        ThisTypeMismatch::Error if type_changed => {
+            return Err(EvalAltResult::ErrorSystem(
+                format!(
+                    "`this` changed from `{this_type}` to `{}` after calling `{fn_name}`",
+                    obj.type_name()
+                ),
+                "type mismatch".into(),
+            )
+            .into());
+        }
+        ThisTypeMismatch::Ignore if type_changed => {
+            debug!(target: ROOT, fn_name, source, from = this_type, to = obj.type_name(), "ignore change of type of `this`");
+        }
+        ThisTypeMismatch::Ignore => match from_this(obj, fn_name) {
+            Ok(obj) => *data = obj,
+            Err(err) => {
+                debug!(target: ROOT, fn_name, source, %err, "ignore invalid `this`");
+            }
+        },
+        ThisTypeMismatch::Convert | ThisTypeMismatch::Error => {
+            *data = from_this(obj, fn_name)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Call a function in a compiled script with a Rhai [`Engine`] and a [`Dynamic`] value mapped to
+/// `this`, checking the call first.
+///
+/// Private functions are refused unless allowed in the [options][RunOptions].
+fn call_fn_checked(
+    engine: &Engine,
+    constants: &Scope<'static>,
+    options: &RunOptions,
+    ast: &AST,
+    this: &mut Dynamic,
+    fn_name: &str,
+    arg_values: Vec<Dynamic>,
+) -> RhaiResult<Dynamic> {
+    let source = ast.source();
+
+    if !options.allow_private
+        && ast.iter_functions().any(|f| {
+            f.name == fn_name
+                && f.params.len() == arg_values.len()
+                && f.access == FnAccess::Private
+        })
+    {
+        debug!(target: ROOT, fn_name, source, "refuse to call private function");
+        return Err(EvalAltResult::ErrorFunctionNotFound(
+            format!("{fn_name} (private)"),
+            Position::NONE,
+        )
+        .into());
+    }
+
+    if !ast
+        .iter_functions()
+        .any(|f| options.allow_private || f.access != FnAccess::Private)
+    {
+        let script = source.unwrap_or("<script>");
+        debug!(target: ROOT, fn_name, source, "script defines no callable functions");
+        return Err(EvalAltResult::ErrorFunctionNotFound(
+            format!("{fn_name} (script `{script}` defines no callable functions)"),
+            Position::NONE,
+        )
+        .into());
+    }
+
+    if !ast.iter_functions().any(|f| f.name == fn_name) {
+        let suggestion = ast
+            .iter_functions()
+            .filter(|f| options.allow_private || f.access != FnAccess::Private)
+            .map(|f| (levenshtein(fn_name, f.name), f.name))
+            .filter(|&(d, _)| d <= (fn_name.chars().count() / 3).max(1))
+            .min()
+            .map(|(_, name)| name);
+
+        if let Some(name) = suggestion {
+            debug!(target: ROOT, fn_name, source, suggestion = name, "function not found");
+            return Err(EvalAltResult::ErrorFunctionNotFound(
+                format!("{fn_name} (did you mean `{name}`?)"),
+                Position::NONE,
+            )
+            .into());
+        }
+    } else if !ast
+        .iter_functions()
+        .any(|f| f.name == fn_name && f.params.len() == arg_values.len())
+    {
+        let mut arities: Vec<_> = ast
+            .iter_functions()
+            .filter(|f| f.name == fn_name)
+            .filter(|f| options.allow_private || f.access != FnAccess::Private)
+            .map(|f| f.params.len())
+            .collect();
+        arities.sort_unstable();
+        arities.dedup();
+
+        if !arities.is_empty() {
+            let arities: Vec<_> = arities.iter().map(ToString::to_string).collect();
+            let got = arg_values.len();
+            debug!(target: ROOT, fn_name, source, got, "argument count mismatch");
+            return Err(EvalAltResult::ErrorSystem(
+                format!(
+                    "function `{fn_name}` expects {} args, got {got}",
+                    arities.join(" or ")
+                ),
+                "argument count mismatch".into(),
+            )
+            .into());
+        }
+    }
+
+    if options.check_args {
+        check_args(ast, fn_name, &arg_values);
+    }
+
+    let mut call_options = CallFnOptions::new().bind_this_ptr(this);
+    if let Some(ref tag) = options.tag {
+        call_options = call_options.with_tag(tag.clone());
+    }
+
+    // Route progress events to this call's channel, restoring the outer one afterwards
+    let outer_progress = PROGRESS.with(|p| p.replace(options.progress.clone()));
+    let outer_limits = CALL_LIMITS.with(|l| {
+        l.replace(CallLimits {
+            max_operations: options.max_operations,
+            deadline: options.timeout.map(|t| std::time::Instant::now() + t),
+        })
+    });
+
+    let result = engine
+        .call_fn_with_options::<Dynamic>(
+            call_options,
+            &mut constants.clone(),
+            ast,
+            fn_name,
+            arg_values,
+        )
+        // Results may hold the state returned by `shared()`
+        .map(Dynamic::flatten)
+        .map_err(|err| match *err {
+            // Keep the script source for errors that are reported with context
+            EvalAltResult::ErrorInFunctionCall(f, _, e, Position::NONE)
+                if f == fn_name && !matches!(*e, EvalAltResult::ErrorTooManyOperations(..)) =>
+            {
+                e
+            }
+            _ => err,
+        });
+
+    PROGRESS.with(|p| *p.borrow_mut() = outer_progress);
+    CALL_LIMITS.with(|l| l.set(outer_limits));
+
+    result
+}
+
+/// Calculate the Levenshtein edit distance between two strings.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            let next = (prev + cost).min(row[j] + 1).min(row[j + 1] + 1);
+            prev = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Register random number functions into a Rhai [`Engine`], seeded randomly if no seed is
+/// provided.
+///
+/// The generator is [SplitMix64](https://prng.di.unimi.it/splitmix64.c), which is fast and
+/// deterministic but _not_ cryptographically secure.
+fn register_rng(engine: &mut Engine, seed: Option<u64>) {
+    use std::hash::{BuildHasher, Hasher};
+
+    let seed = seed.unwrap_or_else(|| {
+        std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish()
+    });
+
+    let state = Arc::new(std::sync::Mutex::new(seed));
+
+    let next = move || {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+
+    let f = next.clone();
+    engine.register_fn("rand", move || f() as INT);
+
+    let f = next.clone();
+    engine.register_fn("rand_float", move || (f() >> 11) as FLOAT / (1_u64 << 53) as FLOAT);
+
+    let f = next;
+    engine.register_fn("rand_range", move |start: INT, end: INT| {
+        if start >= end {
+            return start;
+        }
+        let span = end.wrapping_sub(start) as u64;
+        start.wrapping_add((f() % span) as INT)
+    });
+}
+
+/// Check that the serialized (JSON) size of some data does not exceed a maximum, if any.
+///
+/// Serialization stops as soon as the maximum is exceeded.
+fn check_input_size(data: &impl Serialize, max_input_size: Option<u64>) -> RhaiResult<()> {
+    /// Writer that only counts bytes, failing once a limit is exceeded.
+    struct Counter(u64, u64);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len() as u64;
+            if self.0 > self.1 {
+                Err(std::io::ErrorKind::Other.into())
+            } else {
+                Ok(buf.len())
+            }
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let Some(max) = max_input_size else {
+        return Ok(());
+    };
+
+    match serde_json::to_writer(Counter(0, max), data) {
+        Err(err) if err.is_io() => Err(EvalAltResult::ErrorDataTooLarge(
+            format!("input data (maximum {max} bytes)"),
+            Position::NONE,
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Check the length of a BLOB passed to a script against the maximum input size (if any).
+fn check_blob_size(blob: &Blob, max_input_size: Option<u64>) -> RhaiResult<()> {
+    match max_input_size {
+        Some(max) if blob.len() as u64 > max => Err(EvalAltResult::ErrorDataTooLarge(
+            format!("input data (maximum {max} bytes)"),
+            Position::NONE,
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Read a script file for compilation, returning a descriptive error if it cannot be read or is
+/// not valid UTF-8.
+///
+/// As when compiling the file directly, a shebang line (`#!...`) at the start is removed, keeping
+/// the line break so that positions are not shifted.
+fn read_script(path: &Path) -> std::result::Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|err| {
+        format!(
+            "cannot read script file: `{}` ({err})",
+            path.to_string_lossy()
+        )
+    })?;
+
+    let mut source = String::from_utf8(bytes).map_err(|err| {
+        format!(
+            "script file is not valid UTF-8: `{}` (invalid byte at offset {})",
+            path.to_string_lossy(),
+            err.utf8_error().valid_up_to()
+        )
+    })?;
+
+    if source.starts_with("#!") {
+        let end = source.find('\n').unwrap_or(source.len());
+        source.drain(..end);
+    }
+
+    Ok(source)
+}
+
+/// Check the size of a script file against a maximum (if any) before it is read.
+fn check_file_size(path: &Path, max_file_size: Option<u64>) -> std::result::Result<(), String> {
+    let Some(max) = max_file_size else {
+        return Ok(());
+    };
+
+    let len = std::fs::metadata(path).map_err(|err| err.to_string())?.len();
+
+    if len > max {
+        Err(format!(
+            "script file too large: {len} bytes (maximum {max} bytes)"
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Metadata of an HTTP request, packaged for use as `data` in scripts.
+///
+/// This type can be used as an [`Axum`][axum] extractor. It serializes into an object-map with
+/// the fields `method`, `path`, `query` and `headers`.
+///
+/// Each query parameter maps to a string, except for repeated parameters which map to arrays of
+/// strings (in order), same as [`FormData`]. Repeated headers are joined with `, `. Header values
+/// that are not valid UTF-8 are converted lossily.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RequestData {
+    /// HTTP method, e.g. `GET`.
+    pub method: String,
+    /// Path of the request URI.
+    pub path: String,
+    /// Query parameters, with repeated parameters collected into arrays.
+    pub query: serde_json::Map<String, Value>,
+    /// Request headers, keyed by lower-case header name.
+    pub headers: HashMap<String, String>,
+}
+
+impl RequestData {
+    /// Create a [`RequestData`] from the parts of an HTTP request.
+    #[must_use]
+    pub fn from_parts(parts: &Parts) -> Self {
+        let mut query = serde_json::Map::new();
+        let input = parts.uri.query().unwrap_or_default();
+
+        for (name, value) in form_urlencoded::parse(input.as_bytes()) {
+            FormData::push_field(&mut query, name.into_owned(), value.into_owned().into());
+        }
+
+        let mut headers = HashMap::<String, String>::new();
+
+        for (name, value) in &parts.headers {
+            let value = String::from_utf8_lossy(value.as_bytes());
+
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|v| {
+                    v.push_str(", ");
+                    v.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+
+        Self {
+            method: parts.method.to_string(),
+            path: parts.uri.path().to_string(),
+            query,
+            headers,
+        }
+    }
+
+    /// Convert this [`RequestData`] into a JSON object.
+    #[inline(always)]
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestData {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
+
+/// Fields of an `application/x-www-form-urlencoded` (or, with the `multipart` feature,
+/// `multipart/form-data`) request body, packaged as a JSON object for use as `data` in scripts.
+///
+/// This type can be used as an [`Axum`][axum] extractor. Each field maps to a string, except
+/// for repeated fields which map to arrays of strings (in order). For `GET` and `HEAD` requests,
+/// the fields are taken from the query string instead.
+///
+/// # Multipart forms
+///
+/// Multipart forms require the `multipart` feature. Text fields map to strings as above. File
+/// fields map to object maps with the file name, the content type and the size (in bytes) of
+/// the file, e.g. `{ "file_name": "a.png", "content_type": "image/png", "size": 1234 }`.
+/// The content of the file is not included; use Axum's `Multipart` extractor directly to handle
+/// file uploads.
+///
+/// # Limits
+///
+/// The size of the request body is limited by Axum's default body limit (2MB unless changed via
+/// [`DefaultBodyLimit`][axum::extract::DefaultBodyLimit]). Fields are counted while the body is
+/// parsed, and parsing stops with `400 Bad Request` as soon as there are more than
+/// [`MAX_FIELDS`][FormData::MAX_FIELDS] fields. Other content types and malformed multipart
+/// bodies are also rejected with `400 Bad Request`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FormData(pub Value);
+
+impl FormData {
+    /// Maximum number of fields in a form.
+    pub const MAX_FIELDS: usize = 1000;
+
+    /// Create a [`FormData`] from form fields, turning repeated fields into arrays.
+    #[must_use]
+    pub fn from_fields(fields: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut map = serde_json::Map::new();
+
+        for (name, value) in fields {
+            Self::push_field(&mut map, name, value.into());
+        }
+
+        Self(Value::Object(map))
+    }
+
+    /// Add a form field to a JSON object, turning repeated fields into arrays.
+    fn push_field(map: &mut serde_json::Map<String, Value>, name: String, value: Value) {
+        match map.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = std::mem::take(existing);
+                *existing = Value::Array(vec![first, value]);
+            }
+            None => {
+                let _ = map.insert(name, value);
+            }
+        }
+    }
+
+    /// Error for a form with more than [`MAX_FIELDS`][FormData::MAX_FIELDS] fields.
+    fn too_many_fields() -> Error {
+        debug!(target: ROOT, max = Self::MAX_FIELDS, "too many form fields");
+        Error::BadRequest(format!(
+            "too many form fields (maximum {})",
+            Self::MAX_FIELDS
+        ))
+    }
+
+    /// Parse `application/x-www-form-urlencoded` fields, stopping as soon as there are too many.
+    fn from_urlencoded(input: &[u8]) -> Result<Self> {
+        let mut fields = Vec::new();
+
+        for (name, value) in form_urlencoded::parse(input) {
+            if fields.len() == Self::MAX_FIELDS {
+                return Err(Self::too_many_fields());
+            }
+            fields.push((name.into_owned(), value.into_owned()));
+        }
+
+        Ok(Self::from_fields(fields))
+    }
+
+    /// Parse the fields of a `multipart/form-data` request, stopping as soon as there are too
+    /// many.
+    #[cfg(feature = "multipart")]
+    async fn from_multipart<S: Send + Sync>(
+        req: axum::extract::Request,
+        state: &S,
+    ) -> Result<Self> {
+        let mut multipart =
+            <axum::extract::Multipart as axum::extract::FromRequest<S>>::from_request(req, state)
+                .await
+                .map_err(|err| Error::BadRequest(err.body_text()))?;
+
+        let mut map = serde_json::Map::new();
+        let mut count = 0;
+
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|err| Error::BadRequest(err.body_text()))?
+        {
+            count += 1;
+            if count > Self::MAX_FIELDS {
+                return Err(Self::too_many_fields());
+            }
+
+            let name = field.name().unwrap_or_default().to_string();
+            let file_name = field.file_name().map(str::to_string);
+
+            let value = match file_name {
+                Some(file_name) => {
+                    let content_type = field.content_type().map(str::to_string);
+                    let size = field
+                        .bytes()
+                        .await
+                        .map_err(|err| Error::BadRequest(err.body_text()))?
+                        .len();
+                    serde_json::json!({
+                        "file_name": file_name,
+                        "content_type": content_type,
+                        "size": size,
+                    })
+                }
+                None => field
+                    .text()
+                    .await
+                    .map_err(|err| Error::BadRequest(err.body_text()))?
+                    .into(),
+            };
+
+            Self::push_field(&mut map, name, value);
+        }
+
+        Ok(Self(Value::Object(map)))
+    }
+}
+
+impl<S: Send + Sync> axum::extract::FromRequest<S> for FormData {
+    type Rejection = Error;
+
+    async fn from_request(
+        req: axum::extract::Request,
+        state: &S,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        #[cfg(feature = "multipart")]
+        if content_type.starts_with("multipart/form-data") {
+            return Self::from_multipart(req, state).await;
+        }
+
+        if req.method() == axum::http::Method::GET || req.method() == axum::http::Method::HEAD {
+            return Self::from_urlencoded(req.uri().query().unwrap_or_default().as_bytes());
+        }
+
+        if !content_type.starts_with("application/x-www-form-urlencoded") {
+            debug!(target: ROOT, content_type = content_type.as_str(), "not a form request");
+            return Err(Error::BadRequest(
+                "expected request with `Content-Type: application/x-www-form-urlencoded`".into(),
+            ));
+        }
+
+        let body = <axum::body::Bytes as axum::extract::FromRequest<S>>::from_request(req, state)
+            .await
+            .map_err(|err| Error::BadRequest(err.body_text()))?;
+
+        Self::from_urlencoded(&body)
+    }
+}
+
+/// A scripting engine based on [`Rhai`](https://rhai.rs).
+///
+/// # Re-entrancy
+///
+/// Native Rust functions registered into the Rhai [`Engine`] can call back into other scripts
+/// by getting a handle via [`RhaiScript::try_get_instance`] (or [`RhaiScript::get_instance`])
+/// and calling [`run_script`][RhaiScript::run_script] (or friends) on it.
+///
+/// The cache of compiled scripts is locked only while a script is being looked up or compiled,
+/// never during evaluation, so such nested calls do not deadlock.
+///
+/// Internal locks recover from poisoning, so a panic in a native function or callback does not
+/// permanently disable scripting.
+///
+/// However, `print`/`debug` handlers installed via [`set_on_print`][RhaiScript::set_on_print]
+/// or [`set_on_debug`][RhaiScript::set_on_debug] run under a read lock, and so must not
+/// themselves install or restore handlers.
+#[derive(Debug, Clone)]
+pub struct RhaiScript {
+    /// Paths to the directories containing Rhai scripts, searched in order.
+    scripts_paths: Arc<Vec<PathBuf>>,
+    /// Cache of compiled Rhai scripts in [`AST`] form.
+    cache: Arc<RwLock<HashMap<PathBuf, Arc<AST>>>>,
+    /// Modification times of the cached script files when they were compiled.
+    modified: Arc<RwLock<HashMap<PathBuf, std::time::SystemTime>>>,
+    /// Handlers for the Rhai `print` and `debug` statements.
+    output: Arc<OutputHandlers>,
+    /// Maximum size (in bytes) of a script file, if any.
+    max_file_size: Option<u64>,
+    /// Maximum serialized size (in bytes) of the data passed to a script, if any.
+    max_input_size: Option<u64>,
+    /// Read-only state shared by all scripts via the `shared` function.
+    shared_state: Arc<RwLock<Dynamic>>,
+    /// Hook called on each compiled script before it is cached, if any.
+    on_compile: Option<CompileHook>,
+    /// Constants available to scripts at compile time.
+    constants: Arc<Scope<'static>>,
+    /// Is script execution enabled?
+    enabled: Arc<AtomicBool>,
+    /// Hook called on script compilation events, if any.
+    on_compile_event: Option<CompileEventHook>,
+    /// Function applied to every successful script result, if any.
+    finalizer: Option<Finalizer>,
+    /// Canonicalize script paths before caching?
+    canonicalize_paths: bool,
+    /// Hook called before every script call, if any.
+    before_call: Option<BeforeCallHook>,
+    /// Hook called after every script call, if any.
+    after_call: Option<AfterCallHook>,
+    /// Limit on the number of concurrent script calls, if any.
+    concurrency: Option<Arc<ConcurrencyLimit>>,
+    /// Number of script calls currently running via the concurrency-limited methods.
+    in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    /// Name of the function called by [`run_script_default`][RhaiScript::run_script_default].
+    default_fn_name: Arc<str>,
+    /// Last error of each script file, if error tracking is enabled.
+    last_errors: Option<Arc<RwLock<HashMap<PathBuf, LastError>>>>,
+    /// Functions from the prelude script, merged into every script, if any.
+    prelude: Option<Arc<AST>>,
+}
+
+impl RhaiScript {
+    /// File extension for Rhai scripts.
+    pub const SCRIPTS_EXT: &'static str = "rhai";
+
+    /// Default name of the function called by [`run_script_default`][RhaiScript::run_script_default].
+    pub const DEFAULT_FN_NAME: &'static str = "main";
+
+    /// Get a new [`RhaiScript`] instance.
+    ///
+    /// The methods [`new`][`RhaiScript::new`] or [`new_with_setup`][`RhaiScript::new_with_setup`] must be called first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called before [`new`][`RhaiScript::new`] or [`new_with_setup`][`RhaiScript::new_with_setup`].
+    #[inline(always)]
+    pub fn get_instance() -> Self {
+        RHAI_SCRIPT.get().unwrap().clone()
+    }
+
+    /// Get a new [`RhaiScript`] instance, or `None` if [`new`][`RhaiScript::new`] or
+    /// [`new_with_setup`][`RhaiScript::new_with_setup`] has not yet been called.
+    ///
+    /// This is useful within native Rust functions registered into the Rhai [`Engine`] that
+    /// need to call other scripts (see [re-entrancy][RhaiScript#re-entrancy]).
+    #[inline(always)]
+    #[must_use]
+    pub fn try_get_instance() -> Option<Self> {
+        RHAI_SCRIPT.get().cloned()
+    }
+
+    /// Create a new [`RhaiScript`] instance for a different scripts directory, sharing the
+    /// global Rhai [`Engine`] with this instance (e.g. one instance per tenant).
+    ///
+    /// The new instance has its own cache of compiled scripts, error tracking and
+    /// [enabled][RhaiScript::set_enabled] state. Everything else (including engine-wide limits,
+    /// the prelude and `shared()` state) is shared, and modules imported by scripts are still
+    /// resolved from the original scripts directories.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the scripts directory does not exist.
+    /// * Error if the scripts path is not a directory.
+    pub fn fork(&self, scripts_path: impl Into<PathBuf>) -> Result<Self> {
+        let scripts_path = scripts_path.into();
+
+        if !scripts_path.exists() {
+            return Err(Error::string(&format!(
+                "missing scripts directory: `{}`",
+                scripts_path.to_string_lossy()
+            )));
+        }
+        if !scripts_path.is_dir() {
+            return Err(Error::string(&format!(
+                "scripts path is not a directory: `{}`",
+                scripts_path.to_string_lossy()
+            )));
+        }
+
+        let mut script = self.clone();
+        script.scripts_paths = Arc::new(vec![scripts_path]);
+        script.cache = Arc::new(RwLock::new(HashMap::new()));
+        script.modified = Arc::new(RwLock::new(HashMap::new()));
+        script.last_errors = self
+            .last_errors
+            .as_ref()
+            .map(|_| Arc::new(RwLock::new(HashMap::new())));
+        script.enabled = Arc::new(AtomicBool::new(self.is_enabled()));
+
+        Ok(script)
+    }
+
+    /// Create a new [`RhaiScript`] instance.
+    ///
+    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the scripts directory does not exist.
+    /// * Error if the scripts path is not a directory.
+    #[inline(always)]
+    pub fn new(scripts_path: impl Into<PathBuf>) -> Result<Self> {
+        Self::new_with_setup(scripts_path, |_| {})
+    }
+
+    /// Create a new [`RhaiScript`] instance with custom setup.
+    ///
+    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the scripts directory does not exist.
+    /// * Error if the scripts path is not a directory.
+    #[inline(always)]
+    pub fn new_with_setup(
+        scripts_path: impl Into<PathBuf>,
+        setup: impl FnOnce(&mut Engine),
+    ) -> Result<Self> {
+        RhaiScriptBuilder::new().build_with_setup(scripts_path, setup)
+    }
+
+    /// Create a new [`RhaiScript`] instance that tolerates a missing scripts directory.
+    ///
+    /// If the scripts directory does not exist, scripting is effectively disabled:
+    /// [`run_script_if_exists`][RhaiScript::run_script_if_exists] always returns `Value::Null`,
+    /// and [`run_script`][RhaiScript::run_script] always returns a script-not-found error.
+    ///
+    /// This method can only be called once. A Rhai [`Engine`] instance is created and shared globally.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    #[inline(always)]
+    pub fn new_optional(scripts_path: impl Into<PathBuf>) -> Result<Self> {
+        RhaiScriptBuilder::new().optional().build(scripts_path)
+    }
+
+    /// Create a new [`RhaiScriptBuilder`] for building a [`RhaiScript`] instance with options.
+    #[inline(always)]
+    #[must_use]
+    pub fn builder() -> RhaiScriptBuilder {
+        RhaiScriptBuilder::new()
+    }
+
+    /// Get a reference to the Rhai [`Engine`].
+    #[inline(always)]
+    #[must_use]
+    pub fn engine(&self) -> &Engine {
+        ENGINE.get().unwrap()
+    }
+
+    /// Override the handler for the Rhai `print` statement.
+    ///
+    /// The previous handler (if any) is returned so that it can be restored later via
+    /// [`restore_on_print`][RhaiScript::restore_on_print].
+    ///
+    /// By default, `print` output is logged at `info` level.
+    pub fn set_on_print(
+        &self,
+        callback: impl Fn(&str) + Send + Sync + 'static,
+    ) -> Option<PrintCallback> {
+        self.output
+            .print
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(Box::new(callback))
+    }
+
+    /// Restore a handler for the Rhai `print` statement previously returned by
+    /// [`set_on_print`][RhaiScript::set_on_print].
+    ///
+    /// Passing `None` restores the default behavior of logging at `info` level.
+    pub fn restore_on_print(&self, callback: Option<PrintCallback>) {
+        *self.output.print.write().unwrap_or_else(PoisonError::into_inner) = callback;
+    }
+
+    /// Override the handler for the Rhai `debug` statement.
+    ///
+    /// The previous handler (if any) is returned so that it can be restored later via
+    /// [`restore_on_debug`][RhaiScript::restore_on_debug].
+    ///
+    /// By default, `debug` output is logged at `debug` level.
+    pub fn set_on_debug(
+        &self,
+        callback: impl Fn(&str, Option<&str>, Position) + Send + Sync + 'static,
+    ) -> Option<DebugCallback> {
+        self.output
+            .debug
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(Box::new(callback))
+    }
+
+    /// Restore a handler for the Rhai `debug` statement previously returned by
+    /// [`set_on_debug`][RhaiScript::set_on_debug].
+    ///
+    /// Passing `None` restores the default behavior of logging at `debug` level.
+    pub fn restore_on_debug(&self, callback: Option<DebugCallback>) {
+        *self.output.debug.write().unwrap_or_else(PoisonError::into_inner) = callback;
+    }
+
+    /// Convert a [Rhai error][EvalAltResult] to a [Loco error][Result].
+    ///
+    /// If the error is a [runtime error][EvalAltResult::ErrorRuntime],
+    /// it is converted using the provided closure.
+    ///
+    /// If the script exceeded the maximum number of operations allowed
+    /// (see [`Engine::set_max_operations`]), it is converted into a `503 Service Unavailable`
+    /// error tagged [`COMPUTE_BUDGET_EXCEEDED`], with the operations limit and the script source
+    /// in the description.
+    ///
+    /// Likewise, if the script exceeded a per-call operations budget or time limit (see
+    /// [`RunOptions::max_operations`] and [`RunOptions::timeout`]), it is converted into a
+    /// `503 Service Unavailable` error tagged [`COMPUTE_BUDGET_EXCEEDED`], naming the limit.
+    ///
+    /// If the script exceeded a [memory limit][MemoryLimits], it is converted into a
+    /// `503 Service Unavailable` error tagged [`MEMORY_LIMIT_EXCEEDED`], naming the limit
+    /// (e.g. `array size limit exceeded`). If the data passed to the script exceeded the
+    /// [maximum input size][RhaiScriptBuilder::with_max_input_size], it is converted into a
+    /// `413 Payload Too Large` error with the same tag.
+    ///
+    /// If scripting is [disabled][RhaiScript::set_enabled], it is converted into a
+    /// `503 Service Unavailable` error tagged [`SCRIPTING_DISABLED`].
+    ///
+    /// If the [maximum number of concurrent script calls][RhaiScriptBuilder::with_max_concurrency]
+    /// is reached, it is converted into a `503 Service Unavailable` error tagged
+    /// [`SCRIPTING_BUSY`].
+    ///
+    /// If the script called `fail(status, message)` (or threw an object map with an integer
+    /// `status` between 400 and 599 and a string `message`), it is converted into an error
+    /// with that HTTP status, tagged [`SCRIPT_FAILED`], with the message as the description.
+    ///
+    /// If the error originated from a [Loco error][Error] converted via [`loco_err_to_rhai`]
+    /// (e.g. in a registered native function), the original Loco error is returned.
+    ///
+    /// Otherwise, the error is converted via [`Error::msg`].
+    pub fn convert_runtime_error<T>(
+        &self,
+        err: Box<EvalAltResult>,
+        converter: impl FnOnce(String) -> Result<T>,
+    ) -> Result<T> {
+        let err = match into_loco_error(err) {
+            Ok(err) => return Err(err),
+            Err(err) => err,
+        };
+
+        if let Some((source, pos)) = find_too_many_operations(&err, None) {
+            let limit = self.engine().max_operations();
+            let message = match source {
+                Some(source) => format!(
+                    "script exceeded compute budget of {limit} operations: `{source}` @ {pos}"
+                ),
+                None => format!("script exceeded compute budget of {limit} operations @ {pos}"),
+            };
+            return Err(Error::CustomError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorDetail::new(COMPUTE_BUDGET_EXCEEDED, message.as_str()),
+            ));
+        }
+
+        if let Some((limit, pos)) = find_call_limit_exceeded(&err) {
+            let message = format!("script exceeded {limit} @ {pos}");
+            return Err(Error::CustomError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorDetail::new(COMPUTE_BUDGET_EXCEEDED, message.as_str()),
+            ));
+        }
+
+        if let Some((typ, pos)) = find_data_too_large(&err) {
+            let (status, message) = if typ.starts_with("input data") {
+                (StatusCode::PAYLOAD_TOO_LARGE, format!("{typ} too large"))
+            } else {
+                let kind = if typ.contains("array") {
+                    "array"
+                } else if typ.contains("map") {
+                    "object map"
+                } else if typ.contains("string") {
+                    "string"
+                } else {
+                    typ
+                };
+                let message = format!("{kind} size limit exceeded @ {pos}");
+                (StatusCode::SERVICE_UNAVAILABLE, message)
+            };
+            return Err(Error::CustomError(
+                status,
+                ErrorDetail::new(MEMORY_LIMIT_EXCEEDED, message.as_str()),
+            ));
+        }
+
+        if matches!(*err, EvalAltResult::ErrorSystem(ref s, _) if s == SCRIPTING_DISABLED) {
+            return Err(Error::CustomError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorDetail::new(SCRIPTING_DISABLED, "scripting is disabled"),
+            ));
+        }
+
+        if matches!(*err, EvalAltResult::ErrorSystem(ref s, _) if s == SCRIPTING_BUSY) {
+            return Err(Error::CustomError(
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorDetail::new(SCRIPTING_BUSY, "too many scripts running"),
+            ));
+        }
+
+        if let Some((status, message)) = find_script_failure(&err) {
+            return Err(Error::CustomError(
+                status,
+                ErrorDetail::new(SCRIPT_FAILED, message.as_str()),
+            ));
+        }
+
+        match *err {
+            EvalAltResult::ErrorRuntime(r, _) => converter(r.to_string()),
+            e => Err(Error::msg(e)),
+        }
+    }
+
+    /// Run a script if it exists.
+    ///
+    /// Return `Value::Null` if the script does not exist.
+    ///
+    /// # Errors
+    ///
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    #[inline(always)]
+    pub fn run_script_if_exists(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        if !self.is_enabled() {
+            debug!(target: ROOT, script = script_file, fn_name, "scripting disabled, skip script");
+            return Ok(Value::Null);
+        }
+
+        self.run_script(script_file, data, fn_name, args)
+            .or_else(|err| match *err {
+                EvalAltResult::ErrorSystem(s, e)
+                    if s == SCRIPT_FILE_NOT_FOUND && e.to_string() == script_file =>
+                {
+                    Ok(Value::Null)
+                }
+                _ => Err(err),
+            })
+    }
+
+    /// Run a script.
+    ///
+    /// Mutations to `data` (mapped to `this` in script) are written back only if the function call
+    /// succeeds. If the function call fails, `data` is left untouched.
+    ///
+    /// [Private][FnAccess::Private] functions cannot be called.
+    /// Use [`call_private_fn`][RhaiScript::call_private_fn] to call them explicitly.
+    ///
+    /// The mutated `this` must still be convertible back into the type of `data`.
+    /// Use `#[serde(deny_unknown_fields)]` on the type of `data` to also catch typos in field
+    /// names (e.g. `this.naem`) which would otherwise silently add new fields.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    ///   The error names the offending field, if any.
+    #[inline(always)]
+    pub fn run_script(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        self.run_script_with_options(RunOptions::new(), script_file, data, fn_name, args)
+    }
+
+    /// Run the default function in a script.
+    ///
+    /// The name of the default function is [`DEFAULT_FN_NAME`][RhaiScript::DEFAULT_FN_NAME]
+    /// unless set via [`RhaiScriptBuilder::with_default_fn_name`].
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    #[inline(always)]
+    pub fn run_script_default(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        self.run_script(script_file, data, &self.default_fn_name, args)
+    }
+
+    /// Get a snapshot of the last error of each script file.
+    ///
+    /// Errors are only tracked if enabled via [`RhaiScriptBuilder::with_error_tracking`].
+    /// Otherwise, this always returns an empty map.
+    ///
+    /// Missing script files are not considered errors.
+    #[must_use]
+    pub fn last_errors(&self) -> HashMap<PathBuf, LastError> {
+        self.last_errors
+            .as_ref()
+            .map(|errors| errors.read().unwrap_or_else(PoisonError::into_inner).clone())
+            .unwrap_or_default()
+    }
+
+    /// Enable or disable script execution (e.g. as a kill switch during an incident).
+    ///
+    /// While disabled, running a script fails with an error tagged [`SCRIPTING_DISABLED`],
+    /// except for [`run_script_if_exists`][RhaiScript::run_script_if_exists] which returns
+    /// `null` as if the script does not exist. Scripting is enabled by default.
+    ///
+    /// See [`kill_switch_routes`] for HTTP endpoints toggling this.
+    #[inline(always)]
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        info!(target: ROOT, enabled, "scripting enabled state changed");
+    }
+
+    /// Is script execution enabled?
+    #[inline(always)]
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Remove a script from the cache of compiled scripts, so that it is recompiled when next
+    /// run.
+    ///
+    /// The script file is resolved to a path in the same way as [`run_script`][RhaiScript::run_script],
+    /// including adding the default extension if none is specified.
+    ///
+    /// Returns `true` if the script was cached.
+    pub fn invalidate(&self, script_file: &str) -> bool {
+        let file = Self::script_file_name(script_file);
+        let mut cache = self.cache.write().unwrap_or_else(PoisonError::into_inner);
+
+        let Some(path) = self
+            .scripts_paths
+            .iter()
+            .map(|root| self.cache_key(root.join(&file)))
+            .find(|path| cache.contains_key(path))
+        else {
+            return false;
+        };
+
+        let _ = cache.remove(&path);
+        let _ = self
+            .modified
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&path);
+
+        debug!(target: ROOT, script = script_file, ?path, "invalidate cached script");
+
+        true
+    }
+
+    /// Get the paths of all the scripts in the cache of compiled scripts, sorted.
+    pub fn cached_scripts(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<_> = self
+            .cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Shut down scripting, clearing the cache of compiled scripts and all tracked errors.
+    ///
+    /// Call this from the application's shutdown hook (e.g. `Hooks::on_shutdown`). It is safe to
+    /// call multiple times. Scripts run afterwards are simply recompiled.
+    pub fn shutdown(&self) {
+        self.cache.write().unwrap_or_else(PoisonError::into_inner).clear();
+        self.modified.write().unwrap_or_else(PoisonError::into_inner).clear();
+
+        if let Some(ref last_errors) = self.last_errors {
+            last_errors.write().unwrap_or_else(PoisonError::into_inner).clear();
+        }
+
+        info!(target: ROOT, "scripting shut down");
+    }
+
+    /// Replace the read-only state shared by all scripts via the `shared()` script function.
+    ///
+    /// Scripts already running keep the previous state; subsequent calls to `shared()` return
+    /// the new state.
+    pub fn update_shared_state(&self, state: impl Into<Dynamic>) {
+        *self.shared_state.write().unwrap_or_else(PoisonError::into_inner) =
+            into_shared_state(state.into());
+        debug!(target: ROOT, "update shared state");
+    }
+
+    /// Run a script, returning structured information on any error.
+    ///
+    /// This is the same as [`run_script`][RhaiScript::run_script], except that errors are
+    /// returned as [`ScriptError`] which contains the line and column where execution failed.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    pub fn run_script_detailed(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> std::result::Result<Value, ScriptError> {
+        self.run_script(script_file, data, fn_name, args)
+            .map_err(|err| {
+                // The script is already cached if it compiled, so do not load it again
+                let source = self
+                    .cached_script(script_file)
+                    .and_then(|(_, ast)| ast.source().map(ToString::to_string))
+                    .unwrap_or_else(|| self.script_path(script_file).to_string_lossy().into());
+
+                ScriptError::new(&err, Some(&source))
+            })
+    }
+
+    /// Call a function in a script, which may be [private][FnAccess::Private].
+    ///
+    /// [`run_script`][RhaiScript::run_script] refuses to call private functions so that script
+    /// internals are not exposed via dynamic dispatch. This method explicitly allows it and is
+    /// intended for unit-testing internal helper functions.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    #[inline(always)]
+    pub fn call_private_fn(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        let options = RunOptions::new().allow_private(true);
+        self.run_script_with_options(options, script_file, data, fn_name, args)
+    }
+
+    /// Check if a script defines a non-[private][FnAccess::Private] function with a given name
+    /// (regardless of the number of parameters).
+    ///
+    /// The script is compiled and cached if necessary, same as [`run_script`][RhaiScript::run_script].
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    pub fn has_function(&self, script_file: &str, fn_name: &str) -> RhaiResult<bool> {
+        let ast = self.load_script(script_file)?;
+
+        Ok(ast
+            .iter_functions()
+            .any(|f| f.name == fn_name && f.access != FnAccess::Private))
+    }
+
+    /// Run the first function, out of a list of function names, that is defined (and not
+    /// [private][FnAccess::Private]) in a script, regardless of the number of parameters.
+    ///
+    /// This is useful for versioned functions, e.g. trying `handle_v2`, then `handle_v1`, then
+    /// `handle`.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if none of the functions is defined, listing all the function names tried.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    pub fn run_script_first_of(
+        &self,
+        script_file: &str,
+        fn_names: &[&str],
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        let ast = self.load_script(script_file)?;
+
+        let Some(fn_name) = fn_names.iter().find(|&&fn_name| {
+            ast.iter_functions()
+                .any(|f| f.name == fn_name && f.access != FnAccess::Private)
+        }) else {
+            let names: Vec<_> = fn_names.iter().map(|name| format!("`{name}`")).collect();
+            debug!(target: ROOT, script = script_file, ?fn_names, "no function found");
+            return Err(EvalAltResult::ErrorFunctionNotFound(
+                format!("none of {}", names.join(", ")),
+                Position::NONE,
+            )
+            .into());
+        };
+
+        self.run_script(script_file, data, fn_name, args)
+    }
+
+    /// Compile a script from source and register it under a virtual script file name.
+    ///
+    /// Subsequent calls to [`run_script`][RhaiScript::run_script] (and friends) with the same
+    /// `script_file` name use this script. Registering a script with the same name again
+    /// replaces it.
+    ///
+    /// # Errors
+    ///
+    /// * Error if a script file with the same name exists under the scripts directory.
+    /// * Error if there is a syntax error during compilation.
+    pub fn register_source(&self, script_file: &str, source: &str) -> RhaiResult<()> {
+        let script_path = self.script_path(script_file);
+
+        if script_path.exists() {
+            return Err(EvalAltResult::ErrorSystem(
+                "script file already exists".to_string(),
+                script_path.to_string_lossy().into(),
+            )
+            .into());
+        }
+
+        let ast = self.engine().compile_with_scope(&self.constants, source)?;
+        self.check_compiled(&ast, script_file)?;
+        let mut ast = self.merge_prelude(ast);
+        ast.set_source(script_file);
+
+        debug!(target: ROOT, script = script_file, "register script source");
+
+        let _ = self
+            .cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(script_path, Arc::new(ast));
+
+        Ok(())
+    }
+
+    /// Get all the Rhai script files under the scripts directories, recursively.
+    ///
+    /// Script files are yielded for each scripts directory in order, sorted by path within each
+    /// scripts directory. Script files shadowed by
+    /// those with the same name in an earlier scripts directory are also included.
+    /// Directories that cannot be read are skipped.
+    pub fn script_files(&self) -> impl Iterator<Item = PathBuf> {
+        let mut files = Vec::new();
+
+        for root in self.scripts_paths.iter() {
+            if let Err(err) = Self::find_script_files(root, true, &mut files) {
+                warn!(target: ROOT, dir = ?root, %err, "cannot read scripts directory");
+            }
+        }
+
+        files.into_iter()
+    }
+
+    /// Check that a script compiles, without caching it.
+    ///
+    /// Compilation stops at the first error. To see all errors in all scripts at once, use
+    /// [`validate_all`][RhaiScript::validate_all].
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if the script file is larger than the maximum file size.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if the script is rejected by the [on-compile hook][RhaiScriptBuilder::on_compile].
+    pub fn validate(&self, script_file: &str) -> RhaiResult<()> {
+        let script_path = self.script_path(script_file);
+
+        if !script_path.exists() {
+            return Err(EvalAltResult::ErrorSystem(
+                SCRIPT_FILE_NOT_FOUND.to_string(),
+                script_file.into(),
+            )
+            .into());
+        }
+
+        self.compile_script(&script_path, &RunOptions::new()).map(|_| ())
+    }
+
+    /// Check that all the Rhai script files under the scripts directories compile, without
+    /// caching them, collecting all errors instead of stopping at the first one.
+    ///
+    /// Each script file goes through the same checks as [`validate`][RhaiScript::validate]
+    /// (file size, UTF-8 encoding, syntax and the
+    /// [on-compile hook][RhaiScriptBuilder::on_compile]), and is reported at most once, with its
+    /// first error. Use [`format_script_errors`] to pretty-print the errors with file and
+    /// position, e.g. for CI.
+    pub fn validate_all(&self) -> Vec<(PathBuf, Box<EvalAltResult>)> {
+        let options = RunOptions::new();
+
+        self.script_files()
+            .filter_map(|path| match self.compile_script(&path, &options) {
+                Ok(_) => None,
+                Err(err) => {
+                    debug!(target: ROOT, file = ?path, %err, "invalid script");
+                    Some((path, err))
+                }
+            })
+            .collect()
+    }
+
+    /// Recompile all the Rhai script files under the scripts directories which changed since
+    /// they were last compiled (based on their modification times).
+    ///
+    /// The reload is all-or-nothing: all script files are compiled into a fresh cache which then
+    /// replaces the current cache in one go. If any script fails to compile, the current cache is
+    /// kept untouched and the errors are reported in [`ReloadReport::errors`].
+    ///
+    /// Scripts registered via [`register_source`][RhaiScript::register_source] are kept.
+    pub fn reload(&self) -> ReloadReport {
+        let options = RunOptions::default();
+        let old_cache = self.cache.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let old_modified = self.modified.read().unwrap_or_else(PoisonError::into_inner).clone();
+
+        let mut report = ReloadReport::default();
+        let mut cache = HashMap::new();
+        let mut modified = HashMap::new();
+
+        for path in self.script_files().map(|path| self.cache_key(path)) {
+            let mtime = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+
+            // Skip the same physical file reached via another path
+            if cache.contains_key(&path) {
+                continue;
+            }
+
+            if let (Some(ast), Some(mtime)) = (old_cache.get(&path), mtime) {
+                if old_modified.get(&path) == Some(&mtime) {
+                    let _ = cache.insert(path.clone(), ast.clone());
+                    let _ = modified.insert(path.clone(), mtime);
+                    report.unchanged.push(path);
+                    continue;
+                }
+            }
+
+            match self.compile_script(&path, &options) {
+                Ok(ast) => {
+                    let _ = cache.insert(path.clone(), Arc::new(ast));
+                    if let Some(mtime) = mtime {
+                        let _ = modified.insert(path.clone(), mtime);
+                    }
+                    report.recompiled.push(path);
+                }
+                Err(err) => report.errors.push((path, err.to_string())),
+            }
+        }
+
+        if !report.errors.is_empty() {
+            warn!(target: ROOT, errors = report.errors.len(), "scripts reload failed, cache kept");
+            report.recompiled.clear();
+            report.unchanged.clear();
+            return report;
+        }
+
+        report.removed = old_modified
+            .keys()
+            .filter(|path| !cache.contains_key(*path))
+            .cloned()
+            .collect();
+        report.removed.sort();
+
+        let mut current = self.cache.write().unwrap_or_else(PoisonError::into_inner);
+
+        // Keep scripts that have no backing file
+        cache.extend(
+            current
+                .drain()
+                .filter(|(path, _)| !path.exists() && !old_modified.contains_key(path)),
+        );
+
+        *current = cache;
+        *self.modified.write().unwrap_or_else(PoisonError::into_inner) = modified;
+
+        info!(
+            target: ROOT,
+            recompiled = report.recompiled.len(),
+            unchanged = report.unchanged.len(),
+            removed = report.removed.len(),
+            "scripts reloaded"
+        );
+
+        report
+    }
+
+    /// Find all the Rhai script files in a directory, optionally recursing into sub-directories.
+    ///
+    /// Directory entries are processed in order of their paths, independent of the platform.
+    fn find_script_files(
+        dir: &Path,
+        recursive: bool,
+        files: &mut Vec<PathBuf>,
+    ) -> std::io::Result<()> {
+        let mut paths = read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.sort();
+
+        for path in paths {
+            if path.is_dir() {
+                if recursive {
+                    Self::find_script_files(&path, true, files)?;
+                } else {
+                    debug!(target: ROOT, dir = ?path.file_name().unwrap_or_default().to_string_lossy(), "skip dir");
+                }
+            } else if path
+                .extension()
+                .map_or(true, |ext| ext.to_string_lossy() != Self::SCRIPTS_EXT)
+            {
+                debug!(target: ROOT, file = ?path.file_name().unwrap_or_default().to_string_lossy(), "skip non-script file");
+            } else {
+                files.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the signature of a function defined in a script.
+    ///
+    /// If there are multiple functions with the same name (but different numbers of parameters),
+    /// the first one defined is returned.
+    ///
+    /// Returns `None` if the function does not exist.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    pub fn function_signature(
+        &self,
+        script_file: &str,
+        fn_name: &str,
+    ) -> RhaiResult<Option<FnSignature>> {
+        let ast = self.load_script(script_file)?;
+
+        Ok(ast
+            .iter_functions()
+            .find(|f| f.name == fn_name)
+            .map(|f| FnSignature::from(&f)))
+    }
+
+    /// Get the signatures of all functions defined in a script, in order of definition.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    pub fn function_signatures(&self, script_file: &str) -> RhaiResult<Vec<FnSignature>> {
+        let ast = self.load_script(script_file)?;
+
+        Ok(ast.iter_functions().map(|f| FnSignature::from(&f)).collect())
+    }
+
+    /// Get the documentation of all functions defined in a script, in order of definition,
+    /// including doc-comments.
+    ///
+    /// Requires the `metadata` feature.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    #[cfg(feature = "metadata")]
+    pub fn script_docs(&self, script_file: &str) -> RhaiResult<Vec<FnDoc>> {
+        let ast = self.load_script(script_file)?;
+
+        Ok(ast
+            .iter_functions()
+            .map(|f| FnDoc {
+                name: f.name.to_string(),
+                params: f.params.iter().map(ToString::to_string).collect(),
+                private: f.access == FnAccess::Private,
+                comments: f.comments.iter().map(ToString::to_string).collect(),
+            })
+            .collect())
+    }
+
+    /// Get the signatures of all native functions registered into the Rhai [`Engine`]
+    /// (e.g. via [`Engine::register_fn`] in the engine setup closure), sorted.
+    ///
+    /// Functions from packages (i.e. the Rhai standard library) are included only if
+    /// `include_packages` is `true`.
+    ///
+    /// This is useful for generating documentation of the functions available to script authors.
+    ///
+    /// Requires the `metadata` feature.
+    #[cfg(feature = "metadata")]
+    #[must_use]
+    pub fn registered_functions(&self, include_packages: bool) -> Vec<String> {
+        let mut signatures = self.engine().gen_fn_signatures(include_packages);
+        signatures.sort();
+        signatures
+    }
+
+    /// Resolve the path of a script file under the scripts directories,
+    /// adding the default extension if none is specified.
+    ///
+    /// The first scripts directory containing the script file wins. If the script file does
+    /// not exist in any scripts directory, the path under the main scripts directory is returned.
+    fn script_path(&self, script_file: &str) -> PathBuf {
+        let file = Self::script_file_name(script_file);
+
+        if let [root] = self.scripts_paths.as_slice() {
+            return root.join(file);
+        }
+
+        self.scripts_paths
+            .iter()
+            .map(|root| root.join(&file))
+            .find(|path| path.exists())
+            .map(|path| {
+                debug!(target: ROOT, script = script_file, path = ?path, "resolve script");
+                path
+            })
+            .unwrap_or_else(|| self.scripts_paths[0].join(&file))
+    }
+
+    /// Merge functions from the prelude script (if any) into a compiled script.
+    fn merge_prelude(&self, ast: AST) -> AST {
+        match self.prelude {
+            Some(ref prelude) => prelude.merge(&ast),
+            None => ast,
+        }
+    }
+
+    /// Add the default extension to a script file name if none is specified.
+    fn script_file_name(script_file: &str) -> PathBuf {
+        let mut file = PathBuf::from(script_file);
+
+        if file.extension().is_none() {
+            file.set_extension(Self::SCRIPTS_EXT);
+        }
+
+        file
+    }
+
+    /// Look up a compiled script (and its path) in the cache, without touching the file system.
+    ///
+    /// The scripts directories are searched in order.
+    fn cached_script(&self, script_file: &str) -> Option<(PathBuf, Arc<AST>)> {
+        let file = Self::script_file_name(script_file);
+        let cache = self.cache.read().unwrap_or_else(PoisonError::into_inner);
+
+        self.scripts_paths.iter().find_map(|root| {
+            let path = self.cache_key(root.join(&file));
+            cache.get(&path).map(|ast| (path, ast.clone()))
+        })
+    }
+
+    /// Get the key of a script path in the cache, canonicalizing it if
+    /// [enabled][RhaiScriptBuilder::canonicalize_paths].
+    ///
+    /// Paths that cannot be canonicalized (e.g. because the file does not exist) are kept as is.
+    fn cache_key(&self, path: PathBuf) -> PathBuf {
+        if self.canonicalize_paths {
+            std::fs::canonicalize(&path).unwrap_or(path)
+        } else {
+            path
+        }
+    }
+
+    /// Get a compiled script from the cache, compiling and caching it if necessary.
+    ///
+    /// The cache lock is not held after this method returns.
+    #[inline(always)]
+    fn load_script(&self, script_file: &str) -> RhaiResult<Arc<AST>> {
+        self.load_script_with_options(script_file, &RunOptions::default())
+    }
+
+    /// Get a compiled script from the cache, compiling (with the specified [options][RunOptions])
+    /// and caching it if necessary.
+    ///
+    /// Cached scripts (including those registered via [`register_source`][RhaiScript::register_source],
+    /// which have no backing file) are served without checking the file system.
+    ///
+    /// The cache lock is not held after this method returns.
+    fn load_script_with_options(
+        &self,
+        script_file: &str,
+        options: &RunOptions,
+    ) -> RhaiResult<Arc<AST>> {
+        if let Some((path, ast)) = self.cached_script(script_file) {
+            self.compile_event(&path, CompileEvent::CacheHit);
+            return Ok(ast);
+        }
+
+        let script_path = self.script_path(script_file);
+
+        if !script_path.exists() {
+            debug!(target: ROOT, script = script_path.to_string_lossy().as_ref(), message = SCRIPT_FILE_NOT_FOUND);
+            return Err(EvalAltResult::ErrorSystem(
+                SCRIPT_FILE_NOT_FOUND.to_string(),
+                script_file.into(),
+            )
+            .into());
+        }
+
+        self.compile_event(&script_path, CompileEvent::CacheMiss);
+
+        let mtime = std::fs::metadata(&script_path).and_then(|m| m.modified()).ok();
+        let start = std::time::Instant::now();
+        let ast = self.compile_script(&script_path, options)?;
+        self.compile_event(
+            &script_path,
+            CompileEvent::Compiled {
+                duration: start.elapsed(),
+            },
+        );
+
+        let key = self.cache_key(script_path);
+
+        if let Some(mtime) = mtime {
+            let _ = self
+                .modified
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(key.clone(), mtime);
+        }
+
+        let ast = self
+            .cache
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(key)
+            .or_insert_with(|| Arc::new(ast))
+            .clone();
+
+        Ok(ast)
+    }
+
+    /// Fire a [compile event][CompileEvent] to the hook set via
+    /// [`RhaiScriptBuilder::on_compile_event`], if any.
+    #[inline(always)]
+    fn compile_event(&self, script_path: &Path, event: CompileEvent) {
+        if let Some(CompileEventHook(ref hook)) = self.on_compile_event {
+            hook(script_path, event);
+        }
+    }
+
+    /// Compile a script file (with the specified [options][RunOptions]), without caching it.
+    fn compile_script(&self, script_path: &Path, options: &RunOptions) -> RhaiResult<AST> {
+        let source = check_file_size(script_path, self.max_file_size)
+            .and_then(|()| read_script(script_path))
+            .map_err(|err| {
+                EvalAltResult::ErrorSystem(script_path.to_string_lossy().into(), err.into())
+            })?;
+
+        let mut ast = self.engine().compile_with_scope(&self.constants, &source)?;
+        if let Some(level) = options.optimization_level {
+            ast = self.engine().optimize_ast(&self.constants, ast, level);
+        }
+        self.check_compiled(&ast, &script_path.to_string_lossy())?;
+        ast = self.merge_prelude(ast);
+        ast.set_source(script_path.to_string_lossy().as_ref());
+
+        Ok(ast)
+    }
+
+    /// Run the [on-compile hook][RhaiScriptBuilder::on_compile] (if any) on a compiled script.
+    fn check_compiled(&self, ast: &AST, script: &str) -> RhaiResult<()> {
+        match self.on_compile {
+            Some(CompileHook(ref hook)) => hook(ast).map_err(|err| {
+                debug!(target: ROOT, script, %err, "script rejected");
+                EvalAltResult::ErrorSystem(format!("script `{script}` rejected"), err.into()).into()
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Run a script with the specified [options][RunOptions].
+    ///
+    /// Mutations to `data` (mapped to `this` in script) are written back only if the function call
+    /// succeeds. If the function call fails, `data` is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    #[inline(always)]
+    pub fn run_script_with_options(
+        &self,
+        options: RunOptions,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        let result_options = options.clone();
+
+        self.call_script(options, script_file, data, fn_name, args)
+            .and_then(|v| self.finish_result(&result_options, &v, fn_name))
+    }
+
+    /// Convert the raw result of a script function into JSON as per the [options][RunOptions],
+    /// then apply the [finalizer][RhaiScriptBuilder::with_finalizer] (if any).
+    fn finish_result(
+        &self,
+        options: &RunOptions,
+        value: &Dynamic,
+        fn_name: &str,
+    ) -> RhaiResult<Value> {
+        if options.reject_non_finite {
+            check_non_finite(value, fn_name)?;
+        }
+
+        let value = if value.is_unit() && options.unit_result == UnitResult::EmptyObject {
+            Value::Object(serde_json::Map::new())
+        } else {
+            let mut value = from_dynamic(value).unwrap();
+            if options.sort_map_keys {
+                sort_map_keys(&mut value);
+            }
+            value
+        };
+
+        Ok(match self.finalizer {
+            Some(Finalizer(ref finalizer)) => finalizer(value),
+            None => value,
+        })
+    }
+
+    /// Serialize the raw result of a script function as JSON into a writer, applying the
+    /// [finalizer][RhaiScriptBuilder::with_finalizer] (if any).
+    ///
+    /// Without a finalizer, the result is serialized directly without building a [`Value`].
+    fn write_result(&self, writer: impl std::io::Write, value: &Dynamic) -> RhaiResult<()> {
+        let result = match self.finalizer {
+            Some(Finalizer(ref finalizer)) => {
+                serde_json::to_writer(writer, &finalizer(from_dynamic(value)?))
+            }
+            None => serde_json::to_writer(writer, value),
+        };
+
+        result.map_err(|err| {
+            EvalAltResult::ErrorSystem("error serializing script result".to_string(), err.into())
+                .into()
+        })
+    }
+
+    /// Run a script with a read-only copy of `data` mapped to `this`.
+    ///
+    /// Unlike [`run_script`][RhaiScript::run_script], `data` is never written back, even if the
+    /// script mutates `this`, making this suitable for pure functions such as predicates.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    pub fn eval_script(
+        &self,
+        script_file: &str,
+        fn_name: &str,
+        data: &(impl Serialize + Debug),
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        let options = RunOptions::new();
+
+        let result = self
+            .load_script_with_options(script_file, &options)
+            .and_then(|ast| {
+                let mut arg_values = Vec::new();
+                args.parse(&mut arg_values);
+
+                debug!(fn_name, ?data, source = ast.source(), "Rhai: evaluate function");
+
+                check_input_size(data, self.max_input_size)?;
+
+                let mut obj = to_dynamic(data)?;
+                let value = self.call_ast_with_this(&options, script_file, &ast, &mut obj, fn_name, arg_values)?;
+
+                self.finish_result(&options, &value, fn_name)
+            });
+
+        if let Err(ref err) = result {
+            self.track_error(script_file, err);
+        }
+
+        result
+    }
+
+    /// Run a script with custom per-call state (e.g. the current user or a database transaction)
+    /// available to native functions via [`NativeCallContext::tag`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// engine.register_fn("current_user", |ctx: NativeCallContext| -> String {
+    ///     ctx.tag()
+    ///         .and_then(|tag| tag.clone().try_cast::<CurrentUser>())
+    ///         .map_or_else(String::new, |user| user.name)
+    /// });
+    ///
+    /// script.run_script_with_tag("orders", &mut order, "checkout", (), Dynamic::from(user))?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    #[inline(always)]
+    pub fn run_script_with_tag(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+        tag: Dynamic,
+    ) -> RhaiResult<Value> {
+        let options = RunOptions::new().tag(tag);
+        self.run_script_with_options(options, script_file, data, fn_name, args)
+    }
+
+    /// Run a script, returning the raw [`Dynamic`] result without converting it into JSON.
+    ///
+    /// This is the lowest-overhead way to run a script, useful for further manipulating the
+    /// result via Rhai's API (e.g. passing it to another script call).
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    #[inline(always)]
+    pub fn run_script_dynamic(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Dynamic> {
+        self.call_script(RunOptions::new(), script_file, data, fn_name, args)
+    }
+
+    /// Run a script, converting the result into a specific type.
+    ///
+    /// The result is converted via `serde`, so a script returning an array (e.g. `[42, "hello"]`)
+    /// can be converted into a Rust tuple (e.g. `(i64, String)`), and an object map into a
+    /// struct.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (total, warnings): (i64, Vec<String>) =
+    ///     script.run_script_as("orders", &mut order, "checkout", ())?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the result cannot be converted into the specified type.
+    pub fn run_script_as<R: DeserializeOwned>(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<R> {
+        let value = self.run_script_dynamic(script_file, data, fn_name, args)?;
+
+        from_dynamic(&value).map_err(|err| {
+            EvalAltResult::ErrorSystem(
+                format!(
+                    "cannot convert result of `{fn_name}` into `{}`",
+                    std::any::type_name::<R>()
+                ),
+                err.into(),
+            )
+            .into()
+        })
+    }
+
+    /// Run a script with a [BLOB][Blob] (byte array) mapped to `this`.
+    ///
+    /// Unlike [`run_script`][RhaiScript::run_script], the bytes are passed to the script directly
+    /// as a Rhai BLOB instead of being converted via `serde` (which turns them into an array of
+    /// integers). Mutations to `blob` are written back only if the function call succeeds.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the BLOB is longer than the
+    ///   [maximum input size][RhaiScriptBuilder::with_max_input_size].
+    /// * Error if the script changes `this` into a value that is not a BLOB.
+    pub fn run_script_with_blob(
+        &self,
+        script_file: &str,
+        blob: &mut Blob,
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Value> {
+        let options = RunOptions::new();
+
+        let result = self
+            .load_script_with_options(script_file, &options)
+            .and_then(|ast| {
+                let mut arg_values = Vec::new();
+                args.parse(&mut arg_values);
+
+                debug!(fn_name, len = blob.len(), source = ast.source(), "Rhai: call function with BLOB");
+
+                check_blob_size(blob, self.max_input_size)?;
+
+                let mut obj = Dynamic::from_blob(blob.clone());
+                let value = self.call_ast_with_this(&options, script_file, &ast, &mut obj, fn_name, arg_values)?;
+
+                *blob = obj.into_blob().map_err(|typ| -> Box<EvalAltResult> {
+                    EvalAltResult::ErrorSystem(
+                        format!("`this` changed from `blob` to `{typ}` after calling `{fn_name}`"),
+                        "type mismatch".into(),
+                    )
+                    .into()
+                })?;
+
+                self.finish_result(&options, &value, fn_name)
+            });
+
+        if let Err(ref err) = result {
+            self.track_error(script_file, err);
+        }
+
+        result
+    }
+
+    /// Run a script asynchronously on Tokio's blocking thread pool, subject to the
+    /// [concurrency limit][RhaiScriptBuilder::with_max_concurrency] (if any).
+    ///
+    /// `data` is moved into the call and returned together with the result, with mutations
+    /// written back only if the function call succeeds.
+    ///
+    /// # Errors
+    ///
+    /// * Error tagged [`SCRIPTING_BUSY`] if the concurrency limit is reached under the
+    ///   [`FailFast`][BusyPolicy::FailFast] policy.
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    pub async fn run_script_async<T>(
+        &self,
+        script_file: &str,
+        mut data: T,
+        fn_name: &str,
+        args: Vec<Dynamic>,
+    ) -> RhaiResult<(Value, T)>
+    where
+        T: Serialize + DeserializeOwned + Debug + Send + 'static,
+    {
+        let permit = match self.concurrency {
+            Some(ref limit) => Some(limit.acquire(script_file).await?),
+            None => None,
+        };
+
+        let script = self.clone();
+        let script_file = script_file.to_string();
+        let fn_name = fn_name.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let _in_flight = InFlightGuard::new(&script.in_flight);
+
+            script
+                .run_script(&script_file, &mut data, &fn_name, args)
+                .map(|value| (value, data))
+        })
+        .await
+        .map_err(|err| -> Box<EvalAltResult> {
+            EvalAltResult::ErrorSystem("script task failed".to_string(), err.into()).into()
+        })?
+    }
+
+    /// Run a script on the current thread, subject to the
+    /// [concurrency limit][RhaiScriptBuilder::with_max_concurrency] (if any).
+    ///
+    /// Under the [`Queue`][BusyPolicy::Queue] policy, this blocks the current thread until the
+    /// script can run, so call it only where blocking is allowed (e.g. in
+    /// [`tokio::task::spawn_blocking`] or in a background worker), never directly within an
+    /// async task.
+    ///
+    /// # Errors
+    ///
+    /// * Error tagged [`SCRIPTING_BUSY`] if the concurrency limit is reached under the
+    ///   [`FailFast`][BusyPolicy::FailFast] policy.
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the mutated `this` cannot be converted back into the type of `data`.
+    pub fn run_script_blocking(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
     ) -> RhaiResult<Value> {
+        let _permit = match self.concurrency {
+            Some(ref limit) => Some(block_on(limit.acquire(script_file))?),
+            None => None,
+        };
+        let _in_flight = InFlightGuard::new(&self.in_flight);
+
         self.run_script(script_file, data, fn_name, args)
-            .or_else(|err| match *err {
-                EvalAltResult::ErrorSystem(s, e)
-                    if s == SCRIPT_FILE_NOT_FOUND && e.to_string() == script_file =>
-                {
-                    Ok(Value::Null)
-                }
-                _ => Err(err),
-            })
     }
 
-    /// Run a script.
+    /// Get the number of script calls currently running via
+    /// [`run_script_async`][RhaiScript::run_script_async] and
+    /// [`run_script_blocking`][RhaiScript::run_script_blocking] (e.g. for metrics).
+    ///
+    /// Calls waiting for the [concurrency limit][RhaiScriptBuilder::with_max_concurrency] are
+    /// not counted.
+    #[inline(always)]
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Run a script, also returning the time taken by the function call.
+    ///
+    /// The duration covers only the function call, excluding the time taken to load and compile
+    /// the script. The result is the same as returned by [`run_script`][RhaiScript::run_script].
     ///
     /// # Errors
     ///
     /// * Error if the script file does not exist.
     /// * Error if there is a syntax error during compilation.
     /// * Error if there is an error during script evaluation.
-    pub fn run_script(
+    pub fn run_script_timed(
         &self,
         script_file: &str,
         data: &mut (impl Serialize + DeserializeOwned + Debug),
         fn_name: &str,
         args: impl FuncArgs,
+    ) -> RhaiResult<(Value, std::time::Duration)> {
+        let options = RunOptions::new();
+
+        let ast = self
+            .load_script_with_options(script_file, &options)
+            .map_err(|err| {
+                self.track_error(script_file, &err);
+                err
+            })?;
+
+        let mut arg_values = Vec::new();
+        args.parse(&mut arg_values);
+
+        let start = std::time::Instant::now();
+        let result = self.call_ast(&options, script_file, &ast, data, fn_name, arg_values);
+        let elapsed = start.elapsed();
+
+        debug!(target: ROOT, script = script_file, fn_name, ?elapsed, "script call timed");
+
+        match result.and_then(|value| self.finish_result(&options, &value, fn_name)) {
+            Ok(value) => Ok((value, elapsed)),
+            Err(err) => {
+                self.track_error(script_file, &err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Run a script with named arguments.
+    ///
+    /// The values in `named` are passed to the function as positional arguments, in the order
+    /// of the function's parameters, matched by name. If the function is overloaded, the
+    /// overload with the most parameters all present in `named` is called.
+    ///
+    /// Keys in `named` that do not match any parameter are ignored with a warning.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if the function does not exist.
+    /// * Error if any parameter of the function is missing from `named`.
+    /// * Error if there is an error during script evaluation.
+    pub fn run_script_named(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        mut named: Map,
     ) -> RhaiResult<Value> {
-        let mut script_path = self.scripts_path.join(script_file);
+        let ast = self.load_script(script_file).map_err(|err| {
+            self.track_error(script_file, &err);
+            err
+        })?;
+
+        let overloads: Vec<_> = ast
+            .iter_functions()
+            .filter(|f| f.name == fn_name && f.access != FnAccess::Private)
+            .collect();
+
+        let Some(func) = overloads
+            .iter()
+            .filter(|f| f.params.iter().all(|&p| named.contains_key(p)))
+            .max_by_key(|f| f.params.len())
+        else {
+            let err: Box<EvalAltResult> = match overloads.first() {
+                Some(f) => {
+                    let missing = f.params.iter().find(|&&p| !named.contains_key(p));
+                    EvalAltResult::ErrorSystem(
+                        format!(
+                            "missing argument `{}` for function `{fn_name}`",
+                            missing.copied().unwrap_or_default()
+                        ),
+                        "missing argument".into(),
+                    )
+                    .into()
+                }
+                None => EvalAltResult::ErrorFunctionNotFound(fn_name.to_string(), Position::NONE)
+                    .into(),
+            };
+            self.track_error(script_file, &err);
+            return Err(err);
+        };
 
-        if script_path.extension().is_none() {
-            script_path.set_extension(Self::SCRIPTS_EXT);
+        let args: Vec<Dynamic> = func
+            .params
+            .iter()
+            .map(|&p| named.remove(p).unwrap_or_default())
+            .collect();
+
+        if !named.is_empty() {
+            let extra: Vec<_> = named.keys().map(|k| k.as_str()).collect();
+            warn!(target: ROOT, script = script_file, fn_name, ?extra, "ignore extra named arguments");
         }
 
-        let _ = trace_span!("run_script").enter();
+        self.run_script(script_file, data, fn_name, args)
+    }
 
-        if !script_path.exists() {
-            debug!(target: ROOT, script = script_path.to_string_lossy().as_ref(), message = SCRIPT_FILE_NOT_FOUND);
-            return Err(EvalAltResult::ErrorSystem(
-                SCRIPT_FILE_NOT_FOUND.to_string(),
-                script_file.into(),
+    /// Run a hook function in every script that defines it.
+    ///
+    /// All Rhai script files under the scripts directories are scanned in order of their paths.
+    /// For each script defining a public function named `fn_name`, that function is called with
+    /// `data` mapped to `this`. Script files shadowed by those with the same name in an earlier
+    /// scripts directory are skipped.
+    ///
+    /// Mutations to `data` by each successful call are visible to the following scripts.
+    ///
+    /// Returns the path and the result of each script called. An error in one script does not
+    /// stop the others from running; scripts that fail to compile are also reported.
+    pub fn run_hook(
+        &self,
+        fn_name: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        args: impl FuncArgs,
+    ) -> Vec<(PathBuf, RhaiResult<Value>)> {
+        let span = trace_span!("run_hook", fn_name);
+        let _guard = span.enter();
+
+        let mut arg_values = Vec::new();
+        args.parse(&mut arg_values);
+
+        // Scripts are loaded by their names relative to the scripts directories, without the
+        // extension (same as passed to other methods) unless the name has another dot in it
+        let mut seen = std::collections::HashSet::new();
+        let mut scripts: Vec<_> = self
+            .script_files()
+            .filter_map(|path| {
+                let file = self
+                    .scripts_paths
+                    .iter()
+                    .find_map(|root| path.strip_prefix(root).ok())
+                    .unwrap_or(&path)
+                    .to_path_buf();
+                let name = file.with_extension("");
+                let file = if name.extension().is_none() { name } else { file };
+                seen.insert(file.clone()).then_some((path, file))
+            })
+            .collect();
+        scripts.sort();
+
+        let options = RunOptions::default();
+        let mut results = Vec::new();
+
+        for (path, file) in scripts {
+            let script_file = file.to_string_lossy();
+
+            let ast = match self.load_script(&script_file) {
+                Ok(ast) => ast,
+                Err(err) => {
+                    self.track_error(&script_file, &err);
+                    results.push((path, Err(err)));
+                    continue;
+                }
+            };
+
+            if !ast
+                .iter_functions()
+                .any(|f| f.name == fn_name && f.access != FnAccess::Private)
+            {
+                continue;
+            }
+
+            let result = self
+                .call_ast(&options, &script_file, &ast, data, fn_name, arg_values.clone())
+                .and_then(|v| self.finish_result(&options, &v, fn_name));
+
+            if let Err(ref err) = result {
+                self.track_error(&script_file, err);
+            }
+
+            results.push((path, result));
+        }
+
+        results
+    }
+
+    /// Run a function in a script over each item in a slice.
+    ///
+    /// The script is loaded once and the same compiled script is reused for all items, each of
+    /// which is mapped to `this` in turn. Mutations to each item are written back only if the
+    /// function call on that item succeeds.
+    ///
+    /// The results for the items are returned in order. An error on one item does not stop
+    /// processing of the others.
+    ///
+    /// Since compiled scripts are shared, items can also be processed in parallel by calling
+    /// this method on chunks of the slice from multiple threads, each with a cloned
+    /// [`RhaiScript`] handle.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    pub fn run_script_many<T: Serialize + DeserializeOwned + Debug>(
+        &self,
+        script_file: &str,
+        fn_name: &str,
+        items: &mut [T],
+        args: impl FuncArgs,
+    ) -> RhaiResult<Vec<RhaiResult<Value>>> {
+        let span = trace_span!("run_script_many");
+        let _guard = span.enter();
+
+        let options = RunOptions::new();
+
+        let ast = self.load_script(script_file).map_err(|err| {
+            self.track_error(script_file, &err);
+            err
+        })?;
+
+        let mut arg_values = Vec::new();
+        args.parse(&mut arg_values);
+
+        let results = items
+            .iter_mut()
+            .map(|item| {
+                self.call_ast(&options, script_file, &ast, item, fn_name, arg_values.clone())
+                    .and_then(|v| self.finish_result(&options, &v, fn_name))
+                    .map_err(|err| {
+                        self.track_error(script_file, &err);
+                        err
+                    })
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    /// Run a script, serializing the result as JSON directly into a writer.
+    ///
+    /// This avoids building an intermediate [`Value`] for the result, which reduces peak memory
+    /// usage when scripts return large results.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the result cannot be serialized or written.
+    pub fn run_script_to_writer(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+        writer: impl std::io::Write,
+    ) -> RhaiResult<()> {
+        let value = self.call_script(RunOptions::new(), script_file, data, fn_name, args)?;
+
+        self.write_result(writer, &value)
+    }
+
+    /// Run a script, serializing the result directly into a JSON HTTP response.
+    ///
+    /// This avoids building an intermediate [`Value`] for the result, which reduces peak memory
+    /// usage when scripts return large results.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the result cannot be serialized.
+    #[inline(always)]
+    pub fn run_script_to_response(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Response> {
+        self.run_script_to_response_with_options(
+            RunOptions::new(),
+            script_file,
+            data,
+            fn_name,
+            args,
+        )
+    }
+
+    /// Run a script, turning the result into an HTTP response.
+    ///
+    /// The following result shapes are supported:
+    ///
+    /// * `#{ redirect: "/path" }` or `#{ redirect: "/path", status: 301 }`: a redirect to the
+    ///   location, with the status (default `302 Found`) which must be a redirect status code
+    ///   (301, 302, 303, 307 or 308).
+    /// * Anything else: a `200 OK` JSON response with the result as the body, as per
+    ///   [`run_script_to_response`][RhaiScript::run_script_to_response].
+    ///
+    /// # Example
+    ///
+    /// ```js
+    /// fn route() {
+    ///     if this.beta { #{ redirect: "/beta" + this.path, status: 307 } } else { this }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the redirect location is not a string or the status is not a redirect status code.
+    /// * Error if the result cannot be serialized.
+    pub fn run_script_as_response(
+        &self,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Response> {
+        let value = self.call_script(RunOptions::new(), script_file, data, fn_name, args)?;
+
+        let redirect = value.read_lock::<Map>().and_then(|map| {
+            map.get("redirect")
+                .map(|location| (location.clone(), map.get("status").cloned()))
+        });
+
+        let Some((location, status)) = redirect else {
+            let mut body = Vec::new();
+            self.write_result(&mut body, &value)?;
+            return Ok((
+                [(axum::http::header::CONTENT_TYPE, "application/json")],
+                body,
             )
-            .into());
+                .into_response());
+        };
+
+        let invalid = |message: String| -> Box<EvalAltResult> {
+            EvalAltResult::ErrorSystem(message, "invalid redirect".into()).into()
+        };
+
+        let location = location
+            .into_immutable_string()
+            .map_err(|typ| invalid(format!("redirect location must be a string, not `{typ}`")))?;
+        let location = axum::http::HeaderValue::from_str(&location)
+            .map_err(|_| invalid(format!("invalid redirect location: `{location}`")))?;
+
+        let status = match status {
+            None => StatusCode::FOUND,
+            Some(status) => status
+                .as_int()
+                .ok()
+                .and_then(|s| u16::try_from(s).ok())
+                .and_then(|s| StatusCode::from_u16(s).ok())
+                .filter(|s| matches!(s.as_u16(), 301 | 302 | 303 | 307 | 308))
+                .ok_or_else(|| invalid(format!("invalid redirect status: `{status}`")))?,
+        };
+
+        debug!(target: ROOT, script = script_file, fn_name, ?location, %status, "script redirect");
+
+        Ok((status, [(axum::http::header::LOCATION, location)]).into_response())
+    }
+
+    /// Run a script with the specified [options][RunOptions], serializing the result directly
+    /// into a JSON HTTP response.
+    ///
+    /// If the script returns `()` and [`RunOptions::unit_result`] is [`UnitResult::Absent`],
+    /// the response is `204 No Content` with an empty body.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if there is a syntax error during compilation.
+    /// * Error if there is an error during script evaluation.
+    /// * Error if the result cannot be serialized.
+    pub fn run_script_to_response_with_options(
+        &self,
+        options: RunOptions,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Response> {
+        let unit_result = options.unit_result;
+        let reject_non_finite = options.reject_non_finite;
+        let value = self.call_script(options, script_file, data, fn_name, args)?;
+
+        if reject_non_finite {
+            check_non_finite(&value, fn_name)?;
         }
 
-        let mut cache = self.cache.write().unwrap();
+        let value = match unit_result {
+            UnitResult::Absent if value.is_unit() => {
+                return Ok(StatusCode::NO_CONTENT.into_response());
+            }
+            UnitResult::EmptyObject if value.is_unit() => Dynamic::from_map(Map::new()),
+            _ => value,
+        };
 
-        let ast = if let Some(ast) = cache.get(&script_path) {
-            ast
-        } else {
-            let mut ast = self.engine().compile_file(script_path.clone())?;
-            ast.set_source(script_path.to_string_lossy().as_ref());
-            cache
-                .entry(script_path)
-                .or_insert_with(|| Arc::new(ast.clone()))
+        let mut body = Vec::new();
+        self.write_result(&mut body, &value)?;
+
+        Ok((
+            [(axum::http::header::CONTENT_TYPE, "application/json")],
+            body,
+        )
+            .into_response())
+    }
+
+    /// Run a script with the specified [options][RunOptions], returning the raw result.
+    ///
+    /// Errors are recorded if error tracking is enabled.
+    fn call_script(
+        &self,
+        options: RunOptions,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Dynamic> {
+        let result = self.invoke_script(options, script_file, data, fn_name, args);
+
+        if let Err(ref err) = result {
+            self.track_error(script_file, err);
+        }
+
+        result
+    }
+
+    /// Record the last error of a script file, if error tracking is enabled.
+    ///
+    /// Missing script files are not recorded.
+    fn track_error(&self, script_file: &str, err: &EvalAltResult) {
+        let Some(ref last_errors) = self.last_errors else {
+            return;
+        };
+
+        if matches!(
+            err,
+            EvalAltResult::ErrorSystem(s, _)
+                if s == SCRIPT_FILE_NOT_FOUND || s == SCRIPTING_DISABLED || s == SCRIPTING_BUSY
+        ) {
+            return;
+        }
+
+        let script_path = self.script_path(script_file);
+        let source = script_path.to_string_lossy();
+        let error = LastError {
+            error: ScriptError::new(err, Some(source.as_ref())),
+            timestamp: std::time::SystemTime::now(),
         };
+        let _ = last_errors
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(script_path, error);
+    }
+
+    /// Run a script with the specified [options][RunOptions], returning the raw result.
+    fn invoke_script(
+        &self,
+        options: RunOptions,
+        script_file: &str,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        args: impl FuncArgs,
+    ) -> RhaiResult<Dynamic> {
+        // Run within the parent span (if any), so that script logs are correlated with it
+        let _parent = options.parent_span.as_ref().map(tracing::Span::enter);
+        let span = trace_span!("run_script", script = script_file, fn_name);
+        let _guard = span.enter();
+
+        let ast = self.load_script_with_options(script_file, &options)?;
+
+        let mut arg_values = Vec::new();
+        args.parse(&mut arg_values);
 
+        self.call_ast(&options, script_file, &ast, data, fn_name, arg_values)
+    }
+
+    /// Call a function in a compiled script with the specified [options][RunOptions],
+    /// returning the raw result.
+    fn call_ast(
+        &self,
+        options: &RunOptions,
+        script_file: &str,
+        ast: &AST,
+        data: &mut (impl Serialize + DeserializeOwned + Debug),
+        fn_name: &str,
+        arg_values: Vec<Dynamic>,
+    ) -> RhaiResult<Dynamic> {
         let source = ast.source();
+
         debug!(fn_name, ?data, source, "Rhai: call function");
 
+        check_input_size(&*data, self.max_input_size)?;
+
         let mut obj = to_dynamic(&*data).unwrap();
-        let options = CallFnOptions::new().bind_this_ptr(&mut obj);
+        if options.integral_floats_as_int {
+            integral_floats_to_int(&mut obj);
+        }
+        let this_type = obj.type_name();
+
+        let result =
+            self.call_ast_with_this(options, script_file, ast, &mut obj, fn_name, arg_values);
+
+        // Only write back mutations to `data` if the call succeeds
+        let result = result.and_then(|value| {
+            write_back_this(options, data, &obj, this_type, fn_name, source)?;
+            Ok(value)
+        });
+
+        debug!(?result, ?data, fn_name, source, "Rhai: function returns");
+
+        result
+    }
+
+    /// Call a function in a compiled script with a [`Dynamic`] value mapped to `this`.
+    ///
+    /// This is the common path of all script calls, so the hooks set via
+    /// [`before_call`][RhaiScriptBuilder::before_call] and
+    /// [`after_call`][RhaiScriptBuilder::after_call] (if any) are called here.
+    fn call_ast_with_this(
+        &self,
+        options: &RunOptions,
+        script_file: &str,
+        ast: &AST,
+        this: &mut Dynamic,
+        fn_name: &str,
+        arg_values: Vec<Dynamic>,
+    ) -> RhaiResult<Dynamic> {
+        if let Some(BeforeCallHook(ref hook)) = self.before_call {
+            hook(script_file, fn_name);
+        }
+
+        let result = self.call_fn_checked(options, ast, this, fn_name, arg_values);
+
+        let Some(AfterCallHook(ref hook)) = self.after_call else {
+            return result;
+        };
+
+        // The hook sees the result as JSON, but the raw result is returned
+        let mut raw = Dynamic::UNIT;
+        let result = result.map(|value| {
+            let json = from_dynamic(&value).unwrap_or(Value::Null);
+            raw = value;
+            json
+        });
+
+        hook(script_file, fn_name, &result);
+
+        result.map(|_| raw)
+    }
+
+    /// Call a function in a compiled script with a [`Dynamic`] value mapped to `this`, without
+    /// calling the hooks.
+    ///
+    /// Private functions are refused unless allowed in the [options][RunOptions].
+    fn call_fn_checked(
+        &self,
+        options: &RunOptions,
+        ast: &AST,
+        this: &mut Dynamic,
+        fn_name: &str,
+        arg_values: Vec<Dynamic>,
+    ) -> RhaiResult<Dynamic> {
+        if !self.is_enabled() {
+            debug!(target: ROOT, fn_name, source = ast.source(), "scripting disabled, refuse to call function");
+            return Err(EvalAltResult::ErrorSystem(
+                SCRIPTING_DISABLED.to_string(),
+                "scripting disabled".into(),
+            )
+            .into());
+        }
 
-        let result = self
-            .engine()
-            .call_fn_with_options(options, &mut Scope::new(), ast, fn_name, args)
-            .map(|v| from_dynamic(&v).unwrap())
-            .map_err(|err| match *err {
-                EvalAltResult::ErrorInFunctionCall(f, _, e, Position::NONE) if f == fn_name => e,
-                _ => err,
-            });
+        call_fn_checked(self.engine(), &self.constants, options, ast, this, fn_name, arg_values)
+    }
 
-        *data = from_dynamic(&obj).unwrap();
+    /// Register a shared state object into a Rhai [`Engine`], accessible by scripts via a
+    /// function named `fn_name` that takes no parameters.
+    ///
+    /// The state object is shared (not cloned) between all calls. It is exposed to scripts as a
+    /// custom type `Arc<T>`, so getter functions (e.g. via [`Engine::register_get`]) must be
+    /// registered on `Arc<T>` for scripts to access its contents.
+    ///
+    /// Since the Rhai [`Engine`] is global, the state object must be `Send + Sync + 'static`,
+    /// i.e. it cannot hold references to the [`AppContext`]. Clone the required parts out of the
+    /// [`AppContext`] instead (most Loco resources are cheap to clone).
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// ScriptingEngineInitializerWithContext::new_with_setup(|engine, ctx| {
+    ///     let state = Arc::new(MyState::from_app_context(ctx));
+    ///     RhaiScript::register_state(engine, "my_state", state);
+    ///     engine.register_get("name", |s: &mut Arc<MyState>| s.name.clone());
+    /// })
+    /// ```
+    pub fn register_state<T: Send + Sync + 'static>(
+        engine: &mut Engine,
+        fn_name: &str,
+        state: Arc<T>,
+    ) {
+        engine.register_fn(fn_name, move || state.clone());
+    }
 
-        debug!(?result, ?data, fn_name, source, "Rhai: function returns");
+    /// Register an async Rust function into a Rhai [`Engine`], callable synchronously by scripts
+    /// via a function named `fn_name` that takes one parameter (e.g. an object map of arguments).
+    ///
+    /// The handle of the current Tokio runtime is captured when this is called, so call it
+    /// within the runtime, e.g. in the setup closure of an initializer (which runs in
+    /// `after_routes`). Each call from script blocks on the future via
+    /// [`tokio::task::block_in_place`].
+    ///
+    /// # Deadlocks
+    ///
+    /// Blocking requires a multi-threaded Tokio runtime (the default for Loco). On a
+    /// current-thread runtime, calling the function panics. Scripts running inside an async
+    /// handler block that worker thread until the future completes, so prefer running scripts
+    /// that call async functions via [`tokio::task::spawn_blocking`]. Never await, within the
+    /// future, anything that waits on the script itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if not called within a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// ScriptingEngineInitializerWithContext::new_with_setup(|engine, ctx| {
+    ///     let db = ctx.db.clone();
+    ///     RhaiScript::register_async_fn(engine, "find_user", move |id: Dynamic| {
+    ///         let db = db.clone();
+    ///         async move {
+    ///             let id = id.as_int()?;
+    ///             let user = users::Model::find_by_id(&db, id).await.map_err(loco_err_to_rhai)?;
+    ///             to_dynamic(user)
+    ///         }
+    ///     });
+    /// })
+    /// ```
+    pub fn register_async_fn<Fut>(
+        engine: &mut Engine,
+        fn_name: &str,
+        func: impl Fn(Dynamic) -> Fut + Send + Sync + 'static,
+    ) where
+        Fut: std::future::Future<Output = RhaiResult<Dynamic>> + Send + 'static,
+    {
+        let handle = tokio::runtime::Handle::current();
 
-        result
+        engine.register_fn(fn_name, move |args: Dynamic| -> RhaiResult<Dynamic> {
+            tokio::task::block_in_place(|| handle.block_on(func(args)))
+        });
     }
 
     /// Register Tera filters from Rhai scripts.
@@ -299,16 +5298,75 @@ impl RhaiScript {
     /// If the Tera i18n function `t` is provided, it is also registered into the Rhai [`Engine`]
     /// for use in filter scripts.
     ///
+    /// Returns information on all the Tera filters registered.
+    ///
     /// # Errors
     ///
     /// * Error if the filter scripts directory does not exist.
+    /// * Error if the filter scripts path is not a directory.
     /// * Error if there is a syntax error in any script during compilation.
+    #[inline(always)]
     pub fn register_tera_filters(
         tera: &mut TeraView,
         scripts_path: impl AsRef<Path>,
         engine_setup: impl FnOnce(&mut Engine),
         i18n: Option<impl tera::Function + 'static>,
-    ) -> Result<()> {
+    ) -> Result<Vec<FilterInfo>> {
+        Self::register_tera_filters_with_options(
+            tera,
+            scripts_path,
+            engine_setup,
+            i18n,
+            FilterOptions::default(),
+        )
+    }
+
+    /// Register Tera filters from Rhai scripts with the specified [options][FilterOptions].
+    ///
+    /// If the Tera i18n function `t` is provided, it is also registered into the Rhai [`Engine`]
+    /// for use in filter scripts.
+    ///
+    /// Returns information on all the Tera filters registered.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the filter scripts directory does not exist.
+    /// * Error if the filter scripts path is not a directory.
+    /// * Error if any script file is larger than [`FilterOptions::max_file_size`].
+    /// * Error if there is a syntax error in any script during compilation.
+    #[inline(always)]
+    pub fn register_tera_filters_with_options(
+        tera: &mut TeraView,
+        scripts_path: impl AsRef<Path>,
+        engine_setup: impl FnOnce(&mut Engine),
+        i18n: Option<impl tera::Function + 'static>,
+        options: FilterOptions,
+    ) -> Result<Vec<FilterInfo>> {
+        let compiled = Self::compile_tera_filters(scripts_path, engine_setup, i18n, options)?;
+        Ok(compiled.apply_to(tera))
+    }
+
+    /// Compile Tera filters from Rhai scripts with the specified [options][FilterOptions],
+    /// without registering them.
+    ///
+    /// The [compiled filters][CompiledFilters] can then be registered into multiple Tera
+    /// instances (e.g. for web views and for mailers) without recompiling the scripts.
+    ///
+    /// If the Tera i18n function `t` is provided, it is also registered into the Rhai [`Engine`]
+    /// for use in filter scripts.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the filter scripts directory does not exist.
+    /// * Error if the filter scripts path is not a directory.
+    /// * Error if any script file is larger than [`FilterOptions::max_file_size`].
+    /// * Error if there is a syntax error in any script during compilation.
+    pub fn compile_tera_filters(
+        scripts_path: impl AsRef<Path>,
+        engine_setup: impl FnOnce(&mut Engine),
+        i18n: Option<impl tera::Function + 'static>,
+        options: FilterOptions,
+    ) -> Result<CompiledFilters> {
         let path = scripts_path.as_ref();
 
         if !path.exists() {
@@ -317,138 +5375,398 @@ impl RhaiScript {
                 path.to_string_lossy()
             )));
         }
+        if !path.is_dir() {
+            return Err(Error::string(&format!(
+                "scripts path is not a directory: `{}`",
+                path.to_string_lossy()
+            )));
+        }
 
-        let span = trace_span!("register_filters", dir = ?path);
-        let _ = span.enter();
+        let span = trace_span!("compile_filters", dir = ?path);
+        let _guard = span.enter();
 
-        let engine = FILTERS_ENGINE.get_or_init(|| {
-            let mut engine = Engine::new();
+        let engine = filters_engine(engine_setup, i18n, options.common_setup.as_ref());
 
-            engine_setup(&mut engine);
+        let mut filters = Vec::new();
 
-            engine
-                .on_print(|message| info!(target: ROOT, message))
-                .on_debug(
-                    |message, source, pos| debug!(target: ROOT, ?message, source, position = ?pos),
-                );
-
-            if let Some(i18n) = i18n {
-                let i18n = Arc::new(i18n);
-
-                let t = i18n.clone();
-                engine.register_fn("t", move |args: Map| -> RhaiResult<Dynamic> {
-                    let map: HashMap<String, Value> = args
-                        .into_iter()
-                        .map(|(k, v)| -> RhaiResult<(String, Value)> {
-                            Ok((k.to_string(), from_dynamic(&v)?))
-                        })
-                        .collect::<RhaiResult<_>>()?;
-                    match t.call(&map) {
-                        Ok(v) => Ok(to_dynamic(v)?),
-                        Err(e) => Err(e.to_string().into()),
-                    }
-                });
+        let mut scripts = Vec::new();
+        Self::find_script_files(path, false, &mut scripts)?;
 
-                let t = i18n.clone();
-                engine.register_fn("t", move |key: &str, lang: &str| -> RhaiResult<Dynamic> {
-                    let mut map = HashMap::new();
-                    let _ = map.insert("key".to_string(), key.into());
-                    let _ = map.insert("lang".to_string(), lang.into());
-                    match t.call(&map) {
-                        Ok(v) => Ok(to_dynamic(v)?),
-                        Err(e) => Err(e.to_string().into()),
-                    }
-                });
+        for script in scripts {
+            filters.extend(compile_filter_script(engine, &script, &options)?);
+        }
 
-                info!(target: ROOT, "i18n function loaded into Rhai engine");
-            }
+        let filters = resolve_filter_collisions(filters, options.collision)?;
 
-            engine
-        });
+        Ok(CompiledFilters { engine, filters })
+    }
 
-        for entry in read_dir(path)? {
-            let entry = entry?;
-            let script = entry.path();
+    /// Recompile a single Tera filter script and re-register its filters into a Tera instance,
+    /// replacing the previous versions of those filters. Filters from other script files are not
+    /// touched.
+    ///
+    /// This is useful for hot-reloading filters during development.
+    ///
+    /// `options` should be the same [filter options][FilterOptions] used to register the filters,
+    /// and `registered` the information on all the Tera filters registered (as returned by
+    /// [`register_tera_filters`][RhaiScript::register_tera_filters] and friends, or by a previous
+    /// reload).
+    ///
+    /// The script file is identified by its path, which must match [`FilterInfo::file`] of the
+    /// filters registered from it.
+    ///
+    /// Only the filters already registered from the script file are replaced, keeping their
+    /// names (including any prefix from [`FilterCollision::Prefix`]). New filters in the script
+    /// file whose names are taken by filters from other script files never replace them: they
+    /// are skipped under [`FilterCollision::FirstWins`] and [`FilterCollision::LastWins`],
+    /// registered with a prefix under [`FilterCollision::Prefix`], and an error under
+    /// [`FilterCollision::ErrorOnCollision`].
+    ///
+    /// If the filters engine has not yet been created (via
+    /// [`register_tera_filters`][RhaiScript::register_tera_filters] or friends), it is created
+    /// with the Tera i18n function `t`, if provided. Otherwise, `i18n` is ignored.
+    ///
+    /// Filters removed from the script file stay registered in Tera with their previous versions,
+    /// as Tera cannot unregister filters.
+    ///
+    /// Returns information on all the Tera filters registered, to pass to the next reload.
+    ///
+    /// # Errors
+    ///
+    /// * Error if the script file does not exist.
+    /// * Error if the script file is larger than [`FilterOptions::max_file_size`].
+    /// * Error if there is a syntax error in the script during compilation.
+    /// * Error if a new filter collides with a filter from another script file under
+    ///   [`FilterCollision::ErrorOnCollision`].
+    pub fn reload_tera_filter(
+        tera: &mut TeraView,
+        script_file: impl AsRef<Path>,
+        i18n: Option<impl tera::Function + 'static>,
+        options: &FilterOptions,
+        registered: &[FilterInfo],
+    ) -> Result<Vec<FilterInfo>> {
+        let script = script_file.as_ref();
 
-            if script.is_dir() {
-                debug!(target: ROOT, dir = ?entry.file_name().to_string_lossy(), "skip dir");
-                continue;
-            } else if script
-                .extension()
-                .map_or(true, |ext| ext.to_string_lossy() != Self::SCRIPTS_EXT)
-            {
-                debug!(target: ROOT, file = ?entry.file_name().to_string_lossy(), "skip non-script file");
-                continue;
-            }
+        if !script.is_file() {
+            return Err(Error::string(&format!(
+                "missing filter script file: `{}`",
+                script.to_string_lossy()
+            )));
+        }
 
-            let mut ast = engine.compile_file(script.clone()).map_err(|err| {
-                Error::string(&(format!("`{}`: {err}", entry.file_name().to_string_lossy())))
-            })?;
-            ast.set_source(script.to_string_lossy().as_ref());
-            let shared_ast = Arc::new(ast);
-            debug!(target: ROOT, file = ?entry.file_name().to_string_lossy(), "compile script");
+        let engine = filters_engine(|_| {}, i18n, options.common_setup.as_ref());
+        let compiled = compile_filter_script(engine, script, options)?;
 
-            shared_ast.iter_functions()
-                .filter(|fn_def| fn_def.access != FnAccess::Private && fn_def.params.len() == 1)
-                .for_each(|fn_def| {
-                    let fn_name = fn_def.name.to_string();
-                    let ast = shared_ast.clone();
+        let filters = resolve_reloaded_filters(compiled, script, options.collision, registered)?;
 
-                    let f = move |value: &Value,
-                                  variables: &HashMap<String, Value>|
-                          -> tera::Result<Value> {
-                        trace!(target: ROOT, fn_name, ?value, ?variables, "Rhai: call Tera filter");
+        info!(target: ROOT, file = ?script, filters = filters.len(), "reload Tera filters");
 
-                        let mut obj = to_dynamic(value).unwrap();
-                        let dict = to_dynamic(variables).unwrap().cast::<Map>();
+        let reloaded = CompiledFilters { engine, filters }.apply_to(tera);
 
-                        let scope = &mut Scope::new();
-                        dict.iter().for_each(|(k, v)| {
-                            scope.push_dynamic(k.clone(), v.clone());
-                        });
+        Ok(registered
+            .iter()
+            .filter(|info| info.file != script)
+            .cloned()
+            .chain(reloaded)
+            .collect())
+    }
+}
 
-                        let options = CallFnOptions::new().bind_this_ptr(&mut obj);
-                        let value = engine
-                            .call_fn_with_options::<Dynamic>(options, scope, &ast, &fn_name, (dict,))
-                            .map_err(tera::Error::msg)?;
+/// Get the global Rhai [`Engine`] for Tera filters, creating it if it does not yet exist.
+fn filters_engine(
+    engine_setup: impl FnOnce(&mut Engine),
+    i18n: Option<impl tera::Function + 'static>,
+    common_setup: Option<&EngineSetup>,
+) -> &'static Engine {
+    FILTERS_ENGINE.get_or_init(|| {
+        let mut engine = Engine::new();
 
-                        let value = from_dynamic(&value).unwrap();
-                        trace!(target: ROOT, ?value, fn_name, ?variables, "Rhai: return value from Tera filter");
+        if let Some(setup) = common_setup {
+            setup(&mut engine);
+        }
+        engine_setup(&mut engine);
 
-                        Ok(value)
-                    };
+        engine
+            .on_print(|message| info!(target: ROOT, message))
+            .on_debug(
+                |message, source, pos| debug!(target: ROOT, ?message, source, position = ?pos),
+            );
 
-                    #[cfg(debug_assertions)]
-                    let engine = &mut *tera.tera.lock().expect("lock");
+        if let Some(i18n) = i18n {
+            register_i18n(&mut engine, Arc::new(i18n));
+        }
 
-                    #[cfg(not(debug_assertions))]
-                    let engine = &mut tera.tera;
+        engine
+    })
+}
 
-                    engine.register_filter(fn_def.name, f);
+/// Register the Tera i18n function as the `t` script function into a Rhai [`Engine`].
+///
+/// `t` can be called with an object map of arguments (e.g. `t(#{ key: "hello", lang: "fr" })`)
+/// or with a key and a language (e.g. `t("hello", "fr")`).
+fn register_i18n(engine: &mut Engine, i18n: Arc<dyn tera::Function>) {
+    let t = i18n.clone();
+    engine.register_fn("t", move |args: Map| -> RhaiResult<Dynamic> {
+        let map: HashMap<String, Value> = args
+            .into_iter()
+            .map(|(k, v)| -> RhaiResult<(String, Value)> {
+                Ok((k.to_string(), from_dynamic(&v)?))
+            })
+            .collect::<RhaiResult<_>>()?;
+        match t.call(&map) {
+            Ok(v) => Ok(to_dynamic(v)?),
+            Err(e) => Err(e.to_string().into()),
+        }
+    });
 
-                    info!(target: ROOT, fn_name = fn_def.name, file = ?entry.file_name().to_string_lossy(), "register Tera filter");
-                });
+    let t = i18n;
+    engine.register_fn("t", move |key: &str, lang: &str| -> RhaiResult<Dynamic> {
+        let mut map = HashMap::new();
+        let _ = map.insert("key".to_string(), key.into());
+        let _ = map.insert("lang".to_string(), lang.into());
+        match t.call(&map) {
+            Ok(v) => Ok(to_dynamic(v)?),
+            Err(e) => Err(e.to_string().into()),
         }
+    });
 
-        Ok(())
+    info!(target: ROOT, "i18n function loaded into Rhai engine");
+}
+
+/// Compile a Tera filter script, returning the filters it defines.
+fn compile_filter_script(
+    engine: &Engine,
+    script: &Path,
+    options: &FilterOptions,
+) -> Result<Vec<(FilterInfo, String, Arc<AST>)>> {
+    let mut filters = Vec::new();
+
+    let file_name = script.file_name().unwrap_or_default().to_string_lossy();
+
+    let source = check_file_size(script, options.max_file_size)
+        .and_then(|()| read_script(script))
+        .map_err(|err| Error::string(&format!("`{file_name}`: {err}")))?;
+
+    let mut ast = engine
+        .compile(&source)
+        .map_err(|err| Error::string(&format!("`{file_name}`: {err}")))?;
+    ast.set_source(script.to_string_lossy().as_ref());
+    let shared_ast = Arc::new(ast);
+    debug!(target: ROOT, file = ?file_name, "compile script");
+
+    shared_ast
+        .iter_functions()
+        .filter(|fn_def| fn_def.access != FnAccess::Private && fn_def.params.len() == 1)
+        .filter(|fn_def| options.predicate.as_ref().map_or(true, |p| p(fn_def)))
+        .for_each(|fn_def| {
+            let info = FilterInfo {
+                name: fn_def.name.to_string(),
+                file: script.to_path_buf(),
+            };
+            filters.push((info, fn_def.name.to_string(), shared_ast.clone()));
+        });
+
+    if shared_ast.iter_functions().next().is_none() {
+        warn!(target: ROOT, file = ?file_name, "no Tera filters registered: script defines no functions");
+    } else if filters.is_empty() {
+        warn!(target: ROOT, file = ?file_name, "no Tera filters registered: filter functions must be non-private and take exactly one parameter");
     }
+
+    Ok(filters)
 }
 
 /// Loco initializer for the Rhai scripting engine with custom setup.
-#[derive(Default)]
 pub struct ScriptingEngineInitializerWithSetup<F: Fn(&mut Engine) + Send + Sync + 'static> {
     /// Custom setup for the Rhai [`Engine`], if any.
-    setup: Option<F>,
+    setup: Option<Arc<F>>,
+    /// Initializer options.
+    options: InitializerOptions,
+}
+
+/// Options common to all scripting engine initializers.
+#[derive(Debug, Clone, Default)]
+struct InitializerOptions {
+    /// Script file and function name to run once after the engine is built, if any.
+    bootstrap: Option<(String, String)>,
+    /// Defer building the engine until first use?
+    lazy: bool,
+    /// Tera i18n function to register into the scripts engine, if any.
+    i18n: Option<I18nFunction>,
+}
+
+impl InitializerOptions {
+    /// Create a new [`RhaiScriptBuilder`] with options from a configuration and these options.
+    fn builder(&self, config: &ScriptingEngineInitializerConfig) -> RhaiScriptBuilder {
+        let mut builder = config.builder();
+        if let Some(ref i18n) = self.i18n {
+            builder.i18n = Some(i18n.clone());
+        }
+        builder
+    }
+
+    /// Run post-build actions on the newly-built [`RhaiScript`] instance.
+    fn after_build(&self, engine: &RhaiScript) -> Result<()> {
+        if let Some((ref script_file, ref fn_name)) = self.bootstrap {
+            info!(target: ROOT, script_file, fn_name, "run bootstrap script");
+
+            engine
+                .run_script(script_file, &mut (), fn_name, ())
+                .map_err(|err| {
+                    Error::string(&format!("bootstrap script `{script_file}` failed: {err}"))
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Build the [`RhaiScript`] instance (or defer building it, in lazy mode) and install it
+    /// into the router as an [`Extension`] layer.
+    fn install(
+        &self,
+        router: AxumRouter,
+        build: impl Fn() -> Result<RhaiScript> + Send + Sync + 'static,
+    ) -> Result<AxumRouter> {
+        if self.lazy {
+            let options = self.clone();
+            let lazy = LazyRhaiScript::new(build)
+                .with_bootstrap(move |engine| options.after_build(engine));
+            info!(target: ROOT, "scripting engine to be built on first use");
+            return Ok(router.layer(Extension(ScriptingEngine::from(lazy))));
+        }
+
+        let engine = build()?;
+        self.after_build(&engine)?;
+
+        Ok(router.layer(Extension(ScriptingEngine::from(engine))))
+    }
+}
+
+/// Handle to a [`RhaiScript`] instance that is built on first use, installed by initializers
+/// in [lazy mode][ScriptingEngineInitializerWithSetup::lazy].
+///
+/// Use it in [`Axum`][axum] handlers via the [`ScriptingEngine<LazyRhaiScript>`][ScriptingEngine]
+/// extractor, then call [`get`][LazyRhaiScript::get] to get the [`RhaiScript`] instance.
+#[derive(Clone)]
+pub struct LazyRhaiScript {
+    /// Function building the [`RhaiScript`] instance.
+    build: Arc<dyn Fn() -> Result<RhaiScript> + Send + Sync>,
+    /// Function run once on the built [`RhaiScript`] instance before it is handed out, if any.
+    bootstrap: Option<Arc<dyn Fn(&RhaiScript) -> Result<()> + Send + Sync>>,
+    /// The [`RhaiScript`] instance, once it has been built and bootstrapped.
+    ready: Arc<OnceLock<RhaiScript>>,
+    /// Lock serializing builds, holding the [`RhaiScript`] instance if it has been built but
+    /// bootstrapping it failed.
+    lock: Arc<std::sync::Mutex<Option<RhaiScript>>>,
+}
+
+impl Debug for LazyRhaiScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyRhaiScript")
+            .field("built", &self.ready.get().is_some())
+            .finish()
+    }
+}
+
+impl LazyRhaiScript {
+    /// Create a new [`LazyRhaiScript`] with a function building the [`RhaiScript`] instance.
+    #[inline(always)]
+    #[must_use]
+    pub fn new(build: impl Fn() -> Result<RhaiScript> + Send + Sync + 'static) -> Self {
+        Self {
+            build: Arc::new(build),
+            bootstrap: None,
+            ready: Arc::new(OnceLock::new()),
+            lock: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+    /// Run a function on the [`RhaiScript`] instance once it is built, before it is handed out
+    /// (e.g. to run a bootstrap script).
+    #[must_use]
+    fn with_bootstrap(
+        mut self,
+        bootstrap: impl Fn(&RhaiScript) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.bootstrap = Some(Arc::new(bootstrap));
+        self
+    }
+    /// Get the [`RhaiScript`] instance, building it if this is the first use.
+    ///
+    /// If the [`RhaiScript`] instance has already been built (by any means), it is used
+    /// without calling the build function.
+    ///
+    /// # Errors
+    ///
+    /// Error if building the [`RhaiScript`] instance fails, or if the bootstrap script of the
+    /// initializer fails. Only the failed step is retried on the next call, so a built instance
+    /// is never handed out without having been bootstrapped.
+    pub fn get(&self) -> Result<RhaiScript> {
+        if let Some(script) = self.ready.get() {
+            return Ok(script.clone());
+        }
+
+        let mut built = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Another thread may have built it while waiting for the lock
+        if let Some(script) = self.ready.get() {
+            return Ok(script.clone());
+        }
+
+        let script = match built.take().or_else(RhaiScript::try_get_instance) {
+            Some(script) => script,
+            None => {
+                info!(target: ROOT, "build scripting engine on first use");
+                (self.build)()?
+            }
+        };
+
+        if let Some(ref bootstrap) = self.bootstrap {
+            if let Err(err) = bootstrap(&script) {
+                *built = Some(script);
+                return Err(err);
+            }
+        }
+
+        Ok(self.ready.get_or_init(|| script).clone())
+    }
+}
+
+impl<F: Fn(&mut Engine) + Send + Sync + 'static> Default
+    for ScriptingEngineInitializerWithSetup<F>
+{
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            setup: None,
+            options: InitializerOptions::default(),
+        }
+    }
 }
 
 /// Loco initializer for the Rhai scripting engine.
 pub type ScriptingEngineInitializer = ScriptingEngineInitializerWithSetup<fn(&mut Engine)>;
 
+/// Loco initializer for the Rhai scripting engine with custom setup that has access to the
+/// Loco [`AppContext`].
+///
+/// This is useful for capturing app-specific state (e.g. shared caches or clients) from the
+/// [`AppContext`] and exposing it to scripts via [`RhaiScript::register_state`].
+pub struct ScriptingEngineInitializerWithContext<
+    F: Fn(&mut Engine, &AppContext) + Send + Sync + 'static,
+> {
+    /// Custom setup for the Rhai [`Engine`].
+    setup: Arc<F>,
+    /// Initializer options.
+    options: InitializerOptions,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScriptingEngineInitializerConfig {
     /// Directory containing scripts.
     #[serde(default = "ScriptingEngineInitializerConfig::default_scripts_path")]
     pub scripts_path: PathBuf,
+    /// Additional directories containing scripts, searched in order after `scripts_path`.
+    #[serde(default)]
+    pub additional_scripts_paths: Vec<PathBuf>,
     /// Directory containing Tera filters.
     #[serde(default = "ScriptingEngineInitializerConfig::default_filters_path")]
     pub filters_path: PathBuf,
@@ -459,6 +5777,7 @@ impl Default for ScriptingEngineInitializerConfig {
     fn default() -> Self {
         Self {
             scripts_path: Self::default_scripts_path(),
+            additional_scripts_paths: Vec::new(),
             filters_path: Self::default_filters_path(),
         }
     }
@@ -473,6 +5792,15 @@ impl ScriptingEngineInitializerConfig {
     pub fn default_filters_path() -> PathBuf {
         FILTER_SCRIPTS_DIR.into()
     }
+    /// Create a new [`RhaiScriptBuilder`] with options from this configuration.
+    #[must_use]
+    pub fn builder(&self) -> RhaiScriptBuilder {
+        self.additional_scripts_paths
+            .iter()
+            .fold(RhaiScript::builder(), |builder, path| {
+                builder.with_scripts_path(path.clone())
+            })
+    }
     /// Create a new [`ScriptingEngineInitializerConfig`] instance from the Loco [`AppContext`].
     pub fn from_app_context(ctx: &AppContext) -> Result<Self> {
         let config = ctx
@@ -495,7 +5823,53 @@ impl<F: Fn(&mut Engine) + Send + Sync + 'static> ScriptingEngineInitializerWithS
     #[inline(always)]
     #[must_use]
     pub fn new_with_setup(setup: F) -> Self {
-        Self { setup: Some(setup) }
+        Self {
+            setup: Some(Arc::new(setup)),
+            options: InitializerOptions::default(),
+        }
+    }
+
+    /// Run a function in a bootstrap script once, after the Rhai [`Engine`] is built.
+    ///
+    /// This is useful for one-time script-driven setup (e.g. seeding caches).
+    /// The function takes no parameters, and `this` is `()`.
+    /// Any error from the bootstrap script fails the app boot.
+    #[inline(always)]
+    #[must_use]
+    pub fn bootstrap_script(
+        mut self,
+        script_file: impl Into<String>,
+        fn_name: impl Into<String>,
+    ) -> Self {
+        self.options.bootstrap = Some((script_file.into(), fn_name.into()));
+        self
+    }
+
+    /// Defer building the Rhai [`Engine`] (and running the bootstrap script, if any) until the
+    /// scripting engine is first used, trimming startup cost for apps that rarely use scripting.
+    ///
+    /// In lazy mode, a [`LazyRhaiScript`] is installed instead of a [`RhaiScript`], so handlers
+    /// must use the [`ScriptingEngine<LazyRhaiScript>`][ScriptingEngine] extractor and call
+    /// [`LazyRhaiScript::get`].
+    ///
+    /// The first request using scripting pays the cost of building the engine, and errors
+    /// (e.g. a missing scripts directory or a failed bootstrap script) surface on that request
+    /// instead of failing the app boot. A failed bootstrap script is run again on the next use.
+    #[inline(always)]
+    #[must_use]
+    pub fn lazy(mut self) -> Self {
+        self.options.lazy = true;
+        self
+    }
+
+    /// Register the Tera i18n function as the `t` function available to all scripts.
+    ///
+    /// See [`RhaiScriptBuilder::with_i18n`].
+    #[inline(always)]
+    #[must_use]
+    pub fn with_i18n(mut self, i18n: impl tera::Function + 'static) -> Self {
+        self.options.i18n = Some(I18nFunction(Arc::new(i18n)));
+        self
     }
 }
 
@@ -512,12 +5886,103 @@ impl<F: Fn(&mut Engine) + Send + Sync + 'static> Initializer
     async fn after_routes(&self, router: AxumRouter, ctx: &AppContext) -> Result<AxumRouter> {
         let config = ScriptingEngineInitializerConfig::from_app_context(ctx)?;
 
-        let engine = if let Some(ref setup) = self.setup {
-            RhaiScript::new_with_setup(config.scripts_path.clone(), setup)?
-        } else {
-            RhaiScript::new(config.scripts_path.clone())?
+        let setup = self.setup.clone();
+        let options = self.options.clone();
+        let build = move || {
+            options.builder(&config).build_with_setup(config.scripts_path.clone(), |engine| {
+                if let Some(ref setup) = setup {
+                    setup(engine);
+                }
+            })
         };
 
-        Ok(router.layer(Extension(ScriptingEngine::from(engine))))
+        self.options.install(router, build)
+    }
+}
+
+impl<F: Fn(&mut Engine, &AppContext) + Send + Sync + 'static>
+    ScriptingEngineInitializerWithContext<F>
+{
+    /// Initializer name.
+    pub const NAME: &'static str = ScriptingEngineInitializer::NAME;
+
+    /// Create a new [`ScriptingEngineInitializerWithContext`] instance with custom setup for the
+    /// Rhai [`Engine`] that has access to the Loco [`AppContext`].
+    #[inline(always)]
+    #[must_use]
+    pub fn new_with_setup(setup: F) -> Self {
+        Self {
+            setup: Arc::new(setup),
+            options: InitializerOptions::default(),
+        }
+    }
+
+    /// Run a function in a bootstrap script once, after the Rhai [`Engine`] is built.
+    ///
+    /// This is useful for one-time script-driven setup (e.g. seeding caches).
+    /// The function takes no parameters, and `this` is `()`.
+    /// Any error from the bootstrap script fails the app boot.
+    #[inline(always)]
+    #[must_use]
+    pub fn bootstrap_script(
+        mut self,
+        script_file: impl Into<String>,
+        fn_name: impl Into<String>,
+    ) -> Self {
+        self.options.bootstrap = Some((script_file.into(), fn_name.into()));
+        self
+    }
+
+    /// Defer building the Rhai [`Engine`] (and running the bootstrap script, if any) until the
+    /// scripting engine is first used, trimming startup cost for apps that rarely use scripting.
+    ///
+    /// In lazy mode, a [`LazyRhaiScript`] is installed instead of a [`RhaiScript`], so handlers
+    /// must use the [`ScriptingEngine<LazyRhaiScript>`][ScriptingEngine] extractor and call
+    /// [`LazyRhaiScript::get`].
+    ///
+    /// The first request using scripting pays the cost of building the engine, and errors
+    /// (e.g. a missing scripts directory or a failed bootstrap script) surface on that request
+    /// instead of failing the app boot. A failed bootstrap script is run again on the next use.
+    #[inline(always)]
+    #[must_use]
+    pub fn lazy(mut self) -> Self {
+        self.options.lazy = true;
+        self
+    }
+
+    /// Register the Tera i18n function as the `t` function available to all scripts.
+    ///
+    /// See [`RhaiScriptBuilder::with_i18n`].
+    #[inline(always)]
+    #[must_use]
+    pub fn with_i18n(mut self, i18n: impl tera::Function + 'static) -> Self {
+        self.options.i18n = Some(I18nFunction(Arc::new(i18n)));
+        self
+    }
+}
+
+#[async_trait]
+impl<F: Fn(&mut Engine, &AppContext) + Send + Sync + 'static> Initializer
+    for ScriptingEngineInitializerWithContext<F>
+{
+    #[inline(always)]
+    #[must_use]
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    async fn after_routes(&self, router: AxumRouter, ctx: &AppContext) -> Result<AxumRouter> {
+        let config = ScriptingEngineInitializerConfig::from_app_context(ctx)?;
+
+        let setup = self.setup.clone();
+        let ctx = ctx.clone();
+        let options = self.options.clone();
+        let build = move || {
+            options
+                .builder(&config)
+                .build_with_setup(config.scripts_path.clone(), |engine| setup(engine, &ctx))
+        };
+
+        self.options.install(router, build)
     }
 }
@@ -0,0 +1,67 @@
+//! Macros for declaring script-backed handlers.
+
+/// Re-exports used by macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use axum;
+    pub use loco_rs;
+    pub use serde_json;
+}
+
+/// Declare an [`Axum`][axum] handler that runs a function in a Rhai script.
+///
+/// The generated handler extracts the [`ScriptingEngine`][crate::ScriptingEngine] and a JSON
+/// body of the specified type, runs the script function with the body mapped to `this`, and
+/// returns the result as JSON.
+///
+/// By default, runtime errors thrown by the script are turned into `400 Bad Request` responses.
+/// An optional closure that maps the error message into a Loco `Result` can be provided instead.
+///
+/// Requires the `macros` feature.
+///
+/// # Example
+///
+/// ```ignore
+/// use rhai_loco::script_handler;
+///
+/// script_handler! {
+///     /// Validate a new order.
+///     pub fn validate_order(OrderParams) => "orders", "validate"
+/// }
+///
+/// script_handler! {
+///     pub fn login(LoginParams) => "on_login", "login",
+///         |msg| loco_rs::controller::unauthorized(&msg)
+/// }
+/// ```
+#[macro_export]
+macro_rules! script_handler {
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($body:ty) => $script_file:expr, $fn_name:expr
+    ) => {
+        $crate::script_handler! {
+            $(#[$meta])*
+            $vis fn $name($body) => $script_file, $fn_name,
+                |msg: String| Err($crate::macros::__private::loco_rs::Error::BadRequest(msg))
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis fn $name:ident($body:ty) => $script_file:expr, $fn_name:expr, $converter:expr
+    ) => {
+        $(#[$meta])*
+        $vis async fn $name(
+            $crate::ScriptingEngine(script): $crate::ScriptingEngine<$crate::RhaiScript>,
+            $crate::macros::__private::axum::Json(mut data): $crate::macros::__private::axum::Json<$body>,
+        ) -> $crate::macros::__private::loco_rs::Result<
+            $crate::macros::__private::axum::Json<$crate::macros::__private::serde_json::Value>,
+        > {
+            let value = script
+                .run_script($script_file, &mut data, $fn_name, ())
+                .or_else(|err| script.convert_runtime_error(err, $converter))?;
+
+            Ok($crate::macros::__private::axum::Json(value))
+        }
+    };
+}
@@ -0,0 +1,69 @@
+//! [OpenAPI](https://www.openapis.org) schemas (via [`utoipa`]) for script-backed endpoints.
+//!
+//! Requires the `openapi` feature.
+//!
+//! # Example
+//!
+//! A handler running a fixed script function, documented for OpenAPI:
+//!
+//! ```ignore
+//! use rhai_loco::openapi::{ScriptErrorResponse, ScriptRequest, ScriptResponse};
+//!
+//! #[utoipa::path(
+//!     post,
+//!     path = "/api/orders/validate",
+//!     request_body = ScriptRequest,
+//!     responses(
+//!         (status = 200, description = "Script result", body = ScriptResponse),
+//!         (status = 400, description = "Script error", body = ScriptErrorResponse),
+//!     )
+//! )]
+//! async fn validate_order(
+//!     ScriptingEngine(script): ScriptingEngine<RhaiScript>,
+//!     Json(mut request): Json<ScriptRequest>,
+//! ) -> Result<Json<ScriptResponse>> {
+//!     let args: Vec<Dynamic> = request.args.iter().map(|v| to_dynamic(v).unwrap()).collect();
+//!
+//!     let result = script
+//!         .run_script("orders", &mut request.data, "validate", args)
+//!         .or_else(|err| script.convert_runtime_error(err, |msg| bad_request(&msg)))?;
+//!
+//!     Ok(Json(ScriptResponse { result }))
+//! }
+//! ```
+//!
+//! Avoid taking the script file or function name from the request, which would let clients run
+//! arbitrary scripts.
+
+use super::*;
+use utoipa::ToSchema;
+
+/// Request body of a script-backed endpoint.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, ToSchema)]
+pub struct ScriptRequest {
+    /// Data mapped to `this` in the script.
+    #[schema(value_type = Object)]
+    pub data: Value,
+    /// Arguments passed to the script function. Default empty.
+    #[serde(default)]
+    #[schema(value_type = Vec<Object>)]
+    pub args: Vec<Value>,
+}
+
+/// Response body of a script-backed endpoint.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, ToSchema)]
+pub struct ScriptResponse {
+    /// Result returned by the script function.
+    #[schema(value_type = Object)]
+    pub result: Value,
+}
+
+/// Error response body of a script-backed endpoint, as produced by Loco for errors returned by
+/// [`RhaiScript::convert_runtime_error`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+pub struct ScriptErrorResponse {
+    /// Error tag, e.g. [`SCRIPT_FAILED`][crate::SCRIPT_FAILED].
+    pub error: String,
+    /// Description of the error.
+    pub description: String,
+}
@@ -0,0 +1,149 @@
+//! Helpers for testing script logic without a scripts directory or the global [`RhaiScript`][crate::RhaiScript] instance.
+//!
+//! Requires the `testing` feature.
+//!
+//! Each helper builds an ephemeral Rhai [`Engine`], compiles the script source and runs it,
+//! bypassing the global singleton entirely.
+//!
+//! The ephemeral [`Engine`] has the same functions and callbacks as the scripts engine built by
+//! a default [`RhaiScriptBuilder`][crate::RhaiScriptBuilder] (e.g. the data format functions,
+//! `fail`, `progress`, and `print`/`debug` sent to the log), with `shared()` returning `()`.
+//! Options set on the builder (e.g. limits, random number functions, custom syntax) are not
+//! applied; use the `setup` closure of [`run_inline_with_setup`] for those.
+
+use super::*;
+
+/// Run a function in a script source with an ephemeral Rhai [`Engine`].
+///
+/// `data` is mapped to `this` in script. Mutations to `data` are written back only if the
+/// function call succeeds.
+///
+/// # Errors
+///
+/// * Error if there is a syntax error during compilation.
+/// * Error if there is an error during script evaluation.
+#[inline(always)]
+pub fn run_inline(
+    source: &str,
+    fn_name: &str,
+    data: &mut (impl Serialize + DeserializeOwned + Debug),
+    args: impl FuncArgs,
+) -> RhaiResult<Value> {
+    run_inline_with_setup(source, |_| {}, fn_name, data, args)
+}
+
+/// Run a function in a script source with an ephemeral Rhai [`Engine`] with custom setup.
+///
+/// `data` is mapped to `this` in script. Mutations to `data` are written back only if the
+/// function call succeeds.
+///
+/// # Errors
+///
+/// * Error if there is a syntax error during compilation.
+/// * Error if there is an error during script evaluation.
+/// * Error if the function is [private][FnAccess::Private] or not called with the right
+///   number of arguments.
+/// * Error if `this` cannot be converted back into the type of `data`.
+pub fn run_inline_with_setup(
+    source: &str,
+    setup: impl FnOnce(&mut Engine),
+    fn_name: &str,
+    data: &mut (impl Serialize + DeserializeOwned + Debug),
+    args: impl FuncArgs,
+) -> RhaiResult<Value> {
+    let mut engine = Engine::new();
+    register_defaults(&mut engine, &Arc::default(), &Arc::default(), None);
+    setup(&mut engine);
+
+    let ast = engine.compile(source)?;
+    let options = RunOptions::default();
+
+    let mut arg_values = Vec::new();
+    args.parse(&mut arg_values);
+
+    let mut obj = to_dynamic(&*data)?;
+    let this_type = obj.type_name();
+
+    // Same checks as calls via the scripts engine (e.g. private functions, argument counts)
+    let value = call_fn_checked(
+        &engine,
+        &Scope::new(),
+        &options,
+        &ast,
+        &mut obj,
+        fn_name,
+        arg_values,
+    )?;
+
+    let value = from_dynamic(&value)?;
+    write_back_this(&options, data, &obj, this_type, fn_name, ast.source())?;
+
+    Ok(value)
+}
+
+/// Assert that a function in a script source returns the expected value.
+///
+/// # Panics
+///
+/// * Panics if the script fails to compile or run.
+/// * Panics if the return value is not the expected value.
+#[track_caller]
+pub fn assert_returns(
+    source: &str,
+    fn_name: &str,
+    data: &mut (impl Serialize + DeserializeOwned + Debug),
+    args: impl FuncArgs,
+    expected: impl Into<Value>,
+) {
+    let expected = expected.into();
+
+    match run_inline(source, fn_name, data, args) {
+        Ok(value) => assert_eq!(value, expected, "unexpected return value from `{fn_name}`"),
+        Err(err) => panic!("error running `{fn_name}`: {err}"),
+    }
+}
+
+/// Assert that a function in a script source mutates `data` into the expected value.
+///
+/// # Panics
+///
+/// * Panics if the script fails to compile or run.
+/// * Panics if the mutated `data` is not the expected value.
+#[track_caller]
+pub fn assert_mutates<T: Serialize + DeserializeOwned + Debug + PartialEq>(
+    source: &str,
+    fn_name: &str,
+    mut data: T,
+    args: impl FuncArgs,
+    expected: T,
+) {
+    if let Err(err) = run_inline(source, fn_name, &mut data, args) {
+        panic!("error running `{fn_name}`: {err}");
+    }
+
+    assert_eq!(data, expected, "unexpected data after calling `{fn_name}`");
+}
+
+/// Assert that a function in a script source fails with an error whose message contains the
+/// specified text.
+///
+/// # Panics
+///
+/// * Panics if the script succeeds.
+/// * Panics if the error message does not contain the specified text.
+#[track_caller]
+pub fn assert_fails(
+    source: &str,
+    fn_name: &str,
+    data: &mut (impl Serialize + DeserializeOwned + Debug),
+    args: impl FuncArgs,
+    text: &str,
+) {
+    match run_inline(source, fn_name, data, args) {
+        Ok(value) => panic!("expected `{fn_name}` to fail, but it returned {value}"),
+        Err(err) => assert!(
+            err.to_string().contains(text),
+            "error from `{fn_name}` does not contain `{text}`: {err}"
+        ),
+    }
+}
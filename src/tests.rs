@@ -0,0 +1,905 @@
+//! Tests running scripts through the global [`RhaiScript`] instance.
+//!
+//! The Rhai [`Engine`] is global and can only be set up once, so all tests share one instance
+//! and each test [forks][RhaiScript::fork] it onto its own scripts directory.
+
+use super::*;
+use serde_json::json;
+use std::sync::atomic::AtomicUsize;
+
+/// Directory (relative to the crate root) holding the scripts directories of the tests.
+const TESTS_DIR: &str = "target/rhai-loco-tests";
+
+/// Calls recorded by the [`before_call`][RhaiScriptBuilder::before_call] and
+/// [`after_call`][RhaiScriptBuilder::after_call] hooks of the global [`RhaiScript`] instance.
+static CALLS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+/// Get the calls recorded by the hooks for script files whose names start with `prefix`.
+fn recorded_calls(prefix: &str) -> Vec<String> {
+    CALLS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .filter(|call| call.split(' ').nth(1).unwrap().starts_with(prefix))
+        .cloned()
+        .collect()
+}
+
+/// Get the global [`RhaiScript`] instance, building it on first use.
+fn instance() -> &'static RhaiScript {
+    static INSTANCE: OnceLock<RhaiScript> = OnceLock::new();
+
+    INSTANCE.get_or_init(|| {
+        let dir = Path::new(TESTS_DIR).join(format!("{}-global", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        RhaiScript::builder()
+            .with_error_tracking()
+            .before_call(|script_file, fn_name| {
+                let call = format!("before {script_file} {fn_name}");
+                CALLS
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(call);
+            })
+            .after_call(|script_file, fn_name, result| {
+                let call = format!("after {script_file} {fn_name} {}", result.is_ok());
+                CALLS
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(call);
+            })
+            .build_with_setup(dir, |engine| {
+                engine.register_fn("explode", || -> INT { panic!("explode") });
+                engine.register_fn("opaque", || Opaque);
+            })
+            .unwrap()
+    })
+}
+
+/// Custom type that cannot be converted into JSON, returned by the `opaque` function.
+#[derive(Debug, Clone)]
+struct Opaque;
+
+/// Create a new scripts directory with the specified script files, and fork the global
+/// [`RhaiScript`] instance onto it.
+///
+/// The scripts directory is relative to the crate root, same as the default scripts directory.
+fn scripts(files: &[(&str, &str)]) -> (RhaiScript, PathBuf) {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let dir = Path::new(TESTS_DIR).join(format!("{}-{id}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    for (file, source) in files {
+        std::fs::write(dir.join(file), source).unwrap();
+    }
+
+    (instance().fork(&dir).unwrap(), dir)
+}
+
+#[test]
+fn run_hook_in_sorted_order_collecting_errors() {
+    let (script, dir) = scripts(&[
+        (
+            "d.rhai",
+            r#"fn on_event(n) { this.seen.push("d"); n * 10 }"#,
+        ),
+        ("a.rhai", r#"fn on_event(n) { this.seen.push("a"); n + 1 }"#),
+        ("b.rhai", "fn other() { 0 }"),
+        (
+            "c.rhai",
+            r#"fn on_event(n) { this.seen.push("c"); throw "c failed"; }"#,
+        ),
+    ]);
+    assert!(dir.is_relative());
+
+    let mut data = json!({ "seen": [] });
+    let results = script.run_hook("on_event", &mut data, (1_i64,));
+
+    let paths: Vec<_> = results.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(
+        paths,
+        [dir.join("a.rhai"), dir.join("c.rhai"), dir.join("d.rhai")]
+    );
+
+    assert_eq!(results[0].1.as_ref().unwrap(), &json!(2));
+    let err = results[1].1.as_ref().unwrap_err().to_string();
+    assert!(err.contains("c failed"), "{err}");
+    assert_eq!(results[2].1.as_ref().unwrap(), &json!(10));
+
+    // The failed script does not write back its mutations
+    assert_eq!(data, json!({ "seen": ["a", "d"] }));
+
+    let errors = script.last_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(errors.contains_key(&dir.join("c.rhai")));
+}
+
+#[test]
+fn this_type_change_is_an_error_by_default() {
+    let (script, _) = scripts(&[("retype.rhai", "fn retype() { this = 42; }")]);
+
+    let mut data = json!({ "x": 1 });
+    let err = script
+        .run_script("retype", &mut data, "retype", ())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("`this` changed from `map` to `i64`"), "{err}");
+    assert_eq!(data, json!({ "x": 1 }));
+
+    let options = RunOptions::new().this_type_mismatch(ThisTypeMismatch::Convert);
+    script
+        .run_script_with_options(options, "retype", &mut data, "retype", ())
+        .unwrap();
+    assert_eq!(data, json!(42));
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Point {
+    x: INT,
+    y: INT,
+}
+
+#[test]
+fn failed_call_does_not_write_back_data() {
+    let (script, _) = scripts(&[(
+        "point.rhai",
+        r#"
+            fn move_then_fail() { this.x = 100; this.y += 1; throw "failed"; }
+            fn move_then_break() { this.x = 100; this.y = "oops"; }
+        "#,
+    )]);
+
+    let mut data = Point { x: 1, y: 2 };
+
+    let err = script
+        .run_script("point", &mut data, "move_then_fail", ())
+        .unwrap_err();
+    assert!(err.to_string().contains("failed"), "{err}");
+    assert_eq!(data, Point { x: 1, y: 2 });
+
+    // Nor if the mutated `this` cannot be converted back
+    let err = script
+        .run_script("point", &mut data, "move_then_break", ())
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid `this.y`"), "{err}");
+    assert_eq!(data, Point { x: 1, y: 2 });
+}
+
+#[test]
+fn register_source_without_backing_file() {
+    let (script, dir) = scripts(&[("real.rhai", r#"fn name() { "real" }"#)]);
+
+    script
+        .register_source("virtual", r#"fn name() { "virtual" }"#)
+        .unwrap();
+    assert!(!dir.join("virtual.rhai").exists());
+
+    let value = script.run_script("virtual", &mut (), "name", ()).unwrap();
+    assert_eq!(value, json!("virtual"));
+
+    // A virtual script cannot be registered over an existing script file...
+    let err = script
+        .register_source("real", r#"fn name() { "virtual" }"#)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("script file already exists"),
+        "{err}"
+    );
+
+    // ... but shadows a script file of the same name created afterwards
+    std::fs::write(dir.join("virtual.rhai"), r#"fn name() { "file" }"#).unwrap();
+    let value = script.run_script("virtual", &mut (), "name", ()).unwrap();
+    assert_eq!(value, json!("virtual"));
+}
+
+#[test]
+fn argument_count_mismatch() {
+    let (script, _) = scripts(&[(
+        "math.rhai",
+        "fn add(a, b) { a + b } fn neg(a) { -a } fn neg(a, b) { -a - b }",
+    )]);
+
+    let err = script
+        .run_script("math", &mut (), "add", (1_i64,))
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("function `add` expects 2 args, got 1"),
+        "{err}"
+    );
+
+    let err = script
+        .run_script("math", &mut (), "add", (1_i64, 2_i64, 3_i64))
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("function `add` expects 2 args, got 3"),
+        "{err}"
+    );
+
+    let err = script
+        .run_script("math", &mut (), "neg", ())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("function `neg` expects 1 or 2 args, got 0"),
+        "{err}"
+    );
+
+    let value = script
+        .run_script("math", &mut (), "add", (1_i64, 2_i64))
+        .unwrap();
+    assert_eq!(value, json!(3));
+}
+
+#[test]
+fn panic_in_native_function_does_not_break_scripting() {
+    let (script, _) = scripts(&[
+        ("boom.rhai", "fn boom() { explode() } fn ok() { 1 }"),
+        ("other.rhai", "fn ok() { 2 }"),
+    ]);
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        script.run_script("boom", &mut (), "boom", ())
+    }));
+    assert!(result.is_err());
+
+    // The cached script and the engine are still usable...
+    let value = script.run_script("boom", &mut (), "ok", ()).unwrap();
+    assert_eq!(value, json!(1));
+
+    // ... and so is compiling and caching another script
+    let value = script.run_script("other", &mut (), "ok", ()).unwrap();
+    assert_eq!(value, json!(2));
+    assert_eq!(script.cached_scripts().len(), 2);
+}
+
+#[test]
+fn run_script_as_tuples() {
+    let (script, _) = scripts(&[(
+        "tuples.rhai",
+        r#"fn pair() { [42, "hello"] } fn triple() { [1, 2.5, ["a", "b"]] }"#,
+    )]);
+
+    let pair: (INT, String) = script.run_script_as("tuples", &mut (), "pair", ()).unwrap();
+    assert_eq!(pair, (42, "hello".to_string()));
+
+    let triple: (INT, FLOAT, Vec<String>) = script
+        .run_script_as("tuples", &mut (), "triple", ())
+        .unwrap();
+    assert_eq!(triple, (1, 2.5, vec!["a".to_string(), "b".to_string()]));
+
+    // Arrays of the wrong length do not convert
+    script
+        .run_script_as::<(INT, String, bool)>("tuples", &mut (), "pair", ())
+        .unwrap_err();
+}
+
+#[test]
+fn non_finite_results() {
+    let (script, _) = scripts(&[(
+        "floats.rhai",
+        "fn nan() { 0.0 / 0.0 } fn inf() { 1.0 / 0.0 } fn nested() { #{ items: [1.5, 1.0 / 0.0] } }",
+    )]);
+
+    // Non-finite numbers map to `null` by default
+    let value = script.run_script("floats", &mut (), "nan", ()).unwrap();
+    assert_eq!(value, Value::Null);
+    let value = script.run_script("floats", &mut (), "inf", ()).unwrap();
+    assert_eq!(value, Value::Null);
+    let value = script.run_script("floats", &mut (), "nested", ()).unwrap();
+    assert_eq!(value, json!({ "items": [1.5, null] }));
+
+    let strict = || RunOptions::new().reject_non_finite(true);
+
+    let err = script
+        .run_script_with_options(strict(), "floats", &mut (), "nan", ())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("non-finite number `NaN` at `result`"), "{err}");
+    let err = script
+        .run_script_with_options(strict(), "floats", &mut (), "inf", ())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("non-finite number `inf` at `result`"), "{err}");
+    let err = script
+        .run_script_with_options(strict(), "floats", &mut (), "nested", ())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("non-finite number `inf` at `result.items[1]`"),
+        "{err}"
+    );
+}
+
+#[test]
+fn filter_registration_order() {
+    // Created out of order, but always processed in order of their paths
+    let (_, dir) = scripts(&[
+        ("c.rhai", r#"fn other(vars) { "c" }"#),
+        ("b.rhai", r#"fn fmt(vars) { "b" }"#),
+        ("a.rhai", r#"fn fmt(vars) { "a" }"#),
+    ]);
+
+    let filters = |collision: FilterCollision| -> Vec<(String, PathBuf)> {
+        RhaiScript::compile_tera_filters(
+            &dir,
+            |_| {},
+            None::<fn(&HashMap<String, Value>) -> tera::Result<Value>>,
+            FilterOptions::new().collision(collision),
+        )
+        .unwrap()
+        .filters()
+        .map(|info| (info.name.clone(), info.file.clone()))
+        .collect()
+    };
+
+    assert_eq!(
+        filters(FilterCollision::LastWins),
+        [
+            ("fmt".to_string(), dir.join("b.rhai")),
+            ("other".to_string(), dir.join("c.rhai")),
+        ]
+    );
+    assert_eq!(
+        filters(FilterCollision::FirstWins),
+        [
+            ("fmt".to_string(), dir.join("a.rhai")),
+            ("other".to_string(), dir.join("c.rhai")),
+        ]
+    );
+    assert_eq!(
+        filters(FilterCollision::Prefix),
+        [
+            ("a_fmt".to_string(), dir.join("a.rhai")),
+            ("b_fmt".to_string(), dir.join("b.rhai")),
+            ("other".to_string(), dir.join("c.rhai")),
+        ]
+    );
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum Shape {
+    Empty,
+    Dot(Point),
+    Rect { width: INT, height: INT },
+}
+
+#[test]
+fn internally_tagged_enums_round_trip() {
+    let (script, _) = scripts(&[(
+        "shapes.rhai",
+        r#"
+            fn kind() { this.type }
+
+            fn grow() {
+                switch this.type {
+                    "Empty" => { this = #{ type: "Dot", x: 0, y: 0 }; },
+                    "Dot" => { this.x += 1; this.y += 1; },
+                    "Rect" => { this.width *= 2; this.height *= 2; },
+                }
+            }
+
+            fn invalid() { this.type = "Circle"; }
+        "#,
+    )]);
+
+    let cases = [
+        (Shape::Empty, "Empty", Shape::Dot(Point { x: 0, y: 0 })),
+        (
+            Shape::Dot(Point { x: 1, y: 2 }),
+            "Dot",
+            Shape::Dot(Point { x: 2, y: 3 }),
+        ),
+        (
+            Shape::Rect {
+                width: 2,
+                height: 3,
+            },
+            "Rect",
+            Shape::Rect {
+                width: 4,
+                height: 6,
+            },
+        ),
+    ];
+
+    for (mut shape, kind, grown) in cases {
+        let value = script.run_script("shapes", &mut shape, "kind", ()).unwrap();
+        assert_eq!(value, json!(kind));
+
+        script.run_script("shapes", &mut shape, "grow", ()).unwrap();
+        assert_eq!(shape, grown);
+    }
+
+    // An invalid tag is an error, and the data is not written back
+    let mut shape = Shape::Dot(Point { x: 1, y: 2 });
+    let err = script
+        .run_script("shapes", &mut shape, "invalid", ())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("invalid `this"), "{err}");
+    assert!(err.contains("Circle"), "{err}");
+    assert_eq!(shape, Shape::Dot(Point { x: 1, y: 2 }));
+}
+
+#[test]
+fn hooks_fire_for_all_calls() {
+    let (script, _) = scripts(&[(
+        "hooked.rhai",
+        r#"fn on_event() { 1 } fn fail() { throw "failed"; }"#,
+    )]);
+
+    script
+        .run_script("hooked", &mut (), "on_event", ())
+        .unwrap();
+    script.eval_script("hooked", "on_event", &(), ()).unwrap();
+    script
+        .run_script_many("hooked", "on_event", &mut [(), ()], ())
+        .unwrap();
+    let _ = script.run_hook("on_event", &mut (), ());
+    script
+        .run_script("hooked", &mut (), "fail", ())
+        .unwrap_err();
+
+    // Missing scripts do not fire hooks
+    script
+        .run_script("hooked_missing", &mut (), "on_event", ())
+        .unwrap_err();
+    script
+        .run_script_if_exists("hooked_missing", &mut (), "on_event", ())
+        .unwrap();
+
+    assert_eq!(
+        recorded_calls("hooked"),
+        [
+            // run_script
+            "before hooked on_event",
+            "after hooked on_event true",
+            // eval_script
+            "before hooked on_event",
+            "after hooked on_event true",
+            // run_script_many
+            "before hooked on_event",
+            "after hooked on_event true",
+            "before hooked on_event",
+            "after hooked on_event true",
+            // run_hook
+            "before hooked on_event",
+            "after hooked on_event true",
+            // failed call
+            "before hooked fail",
+            "after hooked fail false",
+        ]
+    );
+}
+
+#[test]
+fn hooks_accept_non_json_results() {
+    let (script, _) = scripts(&[("opaque_hooked.rhai", "fn make() { opaque() }")]);
+
+    let value = script
+        .run_script_dynamic("opaque_hooked", &mut (), "make", ())
+        .unwrap();
+    assert!(value.is::<Opaque>());
+
+    assert_eq!(
+        recorded_calls("opaque_hooked"),
+        ["before opaque_hooked make", "after opaque_hooked make true"]
+    );
+}
+
+#[test]
+fn form_fields_are_limited_while_parsing() {
+    let form = FormData::from_urlencoded(b"a=1&b=2&a=3").unwrap();
+    assert_eq!(form, FormData(json!({ "a": ["1", "3"], "b": "2" })));
+
+    let body = "x=1&".repeat(FormData::MAX_FIELDS);
+    assert!(FormData::from_urlencoded(body.as_bytes()).is_ok());
+
+    let body = "x=1&".repeat(FormData::MAX_FIELDS + 1);
+    assert!(FormData::from_urlencoded(body.as_bytes()).is_err());
+}
+
+#[test]
+fn repeated_query_params_are_collected() {
+    let (parts, _) = axum::http::Request::get("/search?q=a&page=2&q=b%20c")
+        .body(())
+        .unwrap()
+        .into_parts();
+
+    let request = RequestData::from_parts(&parts);
+    assert_eq!(request.path, "/search");
+    assert_eq!(
+        Value::Object(request.query),
+        json!({ "q": ["a", "b c"], "page": "2" })
+    );
+}
+
+#[test]
+fn import_tracking_survives_panics() {
+    type Resolver = CycleDetectingResolver<rhai::module_resolvers::DummyModuleResolver>;
+
+    let result = std::panic::catch_unwind(|| {
+        Resolver::track("a", || -> RhaiResult<()> { panic!("resolve") })
+    });
+    assert!(result.is_err());
+
+    // `a` is no longer being imported after the panic, so this is not a cycle
+    Resolver::track("a", || Ok(())).unwrap();
+
+    let err = Resolver::track("a", || Resolver::track("a", || Ok(())))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("circular import detected"), "{err}");
+}
+
+#[test]
+fn validate_all_runs_the_same_checks_as_validate() {
+    let (script, dir) = scripts(&[
+        ("valid.rhai", "fn ok() { 1 }"),
+        ("syntax.rhai", "fn broken( { 1 }"),
+    ]);
+    std::fs::write(dir.join("utf8.rhai"), b"fn ok() { \"\xff\" }").unwrap();
+
+    let errors = script.validate_all();
+
+    let paths: Vec<_> = errors.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(paths, [dir.join("syntax.rhai"), dir.join("utf8.rhai")]);
+
+    for name in ["syntax", "utf8"] {
+        script.validate(name).unwrap_err();
+    }
+    script.validate("valid").unwrap();
+
+    let report = format_script_errors(&errors);
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let prefix = format!("{}:1:", dir.join("syntax.rhai").to_string_lossy());
+    assert!(lines[0].starts_with(&prefix), "{report}");
+    assert!(lines[1].contains("UTF-8"), "{report}");
+}
+
+#[test]
+fn zero_max_concurrency_is_rejected() {
+    // Rejected before the global engine is set up, so this does not interfere with other tests
+    let err = RhaiScript::builder()
+        .with_max_concurrency(0, BusyPolicy::Queue)
+        .build(TESTS_DIR)
+        .unwrap_err();
+    assert!(err.to_string().contains("at least 1"), "{err}");
+}
+
+#[test]
+fn response_layer_passes_large_bodies_through() {
+    // The layer uses the global instance
+    let dir = &instance().scripts_paths[0];
+    std::fs::write(
+        dir.join("layer.rhai"),
+        "fn transform() { this.transformed = true; }",
+    )
+    .unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let json_response = |body: &'static str| {
+        Response::builder()
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(body))
+            .unwrap()
+    };
+    let body_of = |response: Response| {
+        runtime
+            .block_on(axum::body::to_bytes(response.into_body(), usize::MAX))
+            .unwrap()
+    };
+
+    let layer = RhaiResponseLayer::new("layer", "transform").max_body_size(16);
+
+    let response = runtime.block_on(layer.transform(json_response(r#"{"a":1}"#)));
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = serde_json::from_slice(&body_of(response)).unwrap();
+    assert_eq!(body, json!({ "a": 1, "transformed": true }));
+
+    let large = r#"{"message":"too large to transform"}"#;
+    let response = runtime.block_on(layer.transform(json_response(large)));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_of(response), large.as_bytes());
+}
+
+#[test]
+fn lazy_engine_retries_failed_bootstrap() {
+    // The global instance is already built, so it is used without calling the build function
+    let dir = &instance().scripts_paths[0];
+    let builds = Arc::new(AtomicUsize::new(0));
+
+    let options = InitializerOptions {
+        bootstrap: Some(("lazy_boot".into(), "main".into())),
+        ..InitializerOptions::default()
+    };
+    let counter = builds.clone();
+    let lazy = LazyRhaiScript::new(move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(RhaiScript::get_instance())
+    })
+    .with_bootstrap(move |engine| options.after_build(engine));
+
+    // The bootstrap script does not exist yet, so the instance is not handed out...
+    for _ in 0..2 {
+        let err = lazy.get().unwrap_err().to_string();
+        assert!(err.contains("bootstrap script `lazy_boot` failed"), "{err}");
+    }
+
+    // ... until the bootstrap script succeeds
+    std::fs::write(dir.join("lazy_boot.rhai"), "fn main() { 1 }").unwrap();
+    lazy.get().unwrap();
+
+    // The bootstrap script is not run again once it has succeeded
+    std::fs::remove_file(dir.join("lazy_boot.rhai")).unwrap();
+    lazy.get().unwrap();
+    assert_eq!(builds.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn reloaded_filters_replace_only_their_own() {
+    let (_, dir) = scripts(&[
+        ("a.rhai", r#"fn fmt(vars) { "a" }"#),
+        ("b.rhai", r#"fn fmt(vars) { "b" }"#),
+    ]);
+    let a = dir.join("a.rhai");
+    let b = dir.join("b.rhai");
+
+    let reload = |options: &FilterOptions, registered: &[FilterInfo]| {
+        let engine = filters_engine(
+            |_| {},
+            None::<fn(&HashMap<String, Value>) -> tera::Result<Value>>,
+            None,
+        );
+        let compiled = compile_filter_script(engine, &a, options)?;
+        resolve_reloaded_filters(compiled, &a, options.collision, registered).map(|filters| {
+            filters
+                .into_iter()
+                .map(|(info, ..)| (info.name, info.file))
+                .collect::<Vec<_>>()
+        })
+    };
+    let registered = |options: &FilterOptions| -> Vec<FilterInfo> {
+        RhaiScript::compile_tera_filters(
+            &dir,
+            |_| {},
+            None::<fn(&HashMap<String, Value>) -> tera::Result<Value>>,
+            options.clone(),
+        )
+        .unwrap()
+        .filters()
+        .cloned()
+        .collect()
+    };
+
+    let prefix = registered(&FilterOptions::new().collision(FilterCollision::Prefix));
+    let last_wins = registered(&FilterOptions::new().collision(FilterCollision::LastWins));
+    assert_eq!(
+        last_wins,
+        [FilterInfo {
+            name: "fmt".into(),
+            file: b.clone()
+        }]
+    );
+
+    std::fs::write(&a, r#"fn fmt(vars) { "a2" } fn extra(vars) { "x" }"#).unwrap();
+
+    // Prefixed names stay prefixed
+    let options = FilterOptions::new().collision(FilterCollision::Prefix);
+    assert_eq!(
+        reload(&options, &prefix).unwrap(),
+        [
+            ("a_fmt".to_string(), a.clone()),
+            ("extra".to_string(), a.clone())
+        ]
+    );
+
+    // Filters owned by other files are not replaced
+    let options = FilterOptions::new().collision(FilterCollision::LastWins);
+    assert_eq!(
+        reload(&options, &last_wins).unwrap(),
+        [("extra".to_string(), a.clone())]
+    );
+
+    // The predicate is still applied
+    let options = FilterOptions::new()
+        .collision(FilterCollision::Prefix)
+        .predicate(|f| f.name != "extra");
+    assert_eq!(
+        reload(&options, &prefix).unwrap(),
+        [("a_fmt".to_string(), a.clone())]
+    );
+
+    // New collisions are errors under `ErrorOnCollision`
+    let options = FilterOptions::new().collision(FilterCollision::ErrorOnCollision);
+    let err = reload(&options, &last_wins).unwrap_err().to_string();
+    assert!(
+        err.contains("Tera filter `fmt` defined in multiple scripts"),
+        "{err}"
+    );
+}
+
+#[test]
+fn shared_state_is_not_copied_per_call() {
+    let (script, _) = scripts(&[(
+        "state.rhai",
+        "fn name() { shared().config.name } fn all() { shared() }",
+    )]);
+
+    // The state is shared with the global instance, and not used by other tests
+    let state = json!({ "config": { "name": "app", "tags": ["a", "b"] } });
+    script.update_shared_state(to_dynamic(&state).unwrap());
+
+    let value = script.run_script("state", &mut (), "name", ()).unwrap();
+    assert_eq!(value, json!("app"));
+    let value = script.run_script("state", &mut (), "all", ()).unwrap();
+    assert_eq!(value, state);
+
+    script.update_shared_state(to_dynamic(json!({ "config": { "name": "new" } })).unwrap());
+    let value = script.run_script("state", &mut (), "name", ()).unwrap();
+    assert_eq!(value, json!("new"));
+}
+
+#[test]
+fn finalizer_applies_to_all_json_results() {
+    let (mut script, _) = scripts(&[(
+        "final.rhai",
+        "fn on_final() { #{ b: 2, a: 1 } } fn nothing() { }",
+    )]);
+    script.finalizer = Some(Finalizer(Arc::new(|value| json!({ "data": value }))));
+
+    let expected = json!({ "data": { "a": 1, "b": 2 } });
+
+    let value = script.run_script("final", &mut (), "on_final", ()).unwrap();
+    assert_eq!(value, expected);
+    let value = script.eval_script("final", "on_final", &(), ()).unwrap();
+    assert_eq!(value, expected);
+    let (value, _) = script
+        .run_script_timed("final", &mut (), "on_final", ())
+        .unwrap();
+    assert_eq!(value, expected);
+    let results = script
+        .run_script_many("final", "on_final", &mut [()], ())
+        .unwrap();
+    assert_eq!(results[0].as_ref().unwrap(), &expected);
+    let results = script.run_hook("on_final", &mut (), ());
+    assert_eq!(results[0].1.as_ref().unwrap(), &expected);
+
+    let mut body = Vec::new();
+    script
+        .run_script_to_writer("final", &mut (), "on_final", (), &mut body)
+        .unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&body).unwrap(), expected);
+
+    let options = RunOptions::new().unit_result(UnitResult::EmptyObject);
+    let value = script
+        .run_script_with_options(options, "final", &mut (), "nothing", ())
+        .unwrap();
+    assert_eq!(value, json!({ "data": {} }));
+
+    // The raw result is not finalized
+    let value = script
+        .run_script_dynamic("final", &mut (), "on_final", ())
+        .unwrap();
+    assert!(value.is_map());
+}
+
+#[test]
+fn scripts_are_read_once_with_shebang_removed() {
+    let (script, dir) = scripts(&[(
+        "shebang.rhai",
+        "#!/usr/bin/env rhai-run\nfn ok() { 1 }\nfn broken() { throw \"failed\"; }",
+    )]);
+
+    let value = script.run_script("shebang", &mut (), "ok", ()).unwrap();
+    assert_eq!(value, json!(1));
+
+    // Positions are not shifted by removing the shebang line
+    let err = script
+        .run_script("shebang", &mut (), "broken", ())
+        .unwrap_err();
+    assert_eq!(err.position().line(), Some(3));
+
+    let err = read_script(&dir.join("missing.rhai")).unwrap_err();
+    assert!(err.contains("cannot read script file"), "{err}");
+}
+
+#[test]
+fn compile_events_skip_missing_scripts() {
+    let (mut script, dir) = scripts(&[("events.rhai", "fn ok() { 1 }")]);
+
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    script.on_compile_event = Some(CompileEventHook(Arc::new(move |path, event| {
+        let event = match event {
+            CompileEvent::Compiled { .. } => "compiled",
+            CompileEvent::CacheHit => "hit",
+            CompileEvent::CacheMiss => "miss",
+        };
+        recorded.lock().unwrap().push((path.to_path_buf(), event));
+    })));
+
+    script
+        .run_script("events_missing", &mut (), "ok", ())
+        .unwrap_err();
+    script.run_script("events", &mut (), "ok", ()).unwrap();
+    script.run_script("events", &mut (), "ok", ()).unwrap();
+
+    let path = dir.join("events.rhai");
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            (path.clone(), "miss"),
+            (path.clone(), "compiled"),
+            (path, "hit"),
+        ]
+    );
+}
+
+#[test]
+fn detailed_errors_do_not_load_scripts_again() {
+    let (mut script, dir) = scripts(&[
+        (
+            "detailed.rhai",
+            "fn ok() { 1 }\nfn broken() { throw \"failed\"; }",
+        ),
+        ("detailed_syntax.rhai", "fn broken( { 1 }"),
+    ]);
+
+    let events = Arc::new(AtomicUsize::new(0));
+    let counter = events.clone();
+    script.on_compile_event = Some(CompileEventHook(Arc::new(move |_, _| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    })));
+
+    // Cache miss and compiled
+    let err = script
+        .run_script_detailed("detailed", &mut (), "broken", ())
+        .unwrap_err();
+    assert_eq!(err.line, Some(2));
+    let source = dir.join("detailed.rhai").to_string_lossy().to_string();
+    assert_eq!(err.source, Some(source));
+    assert_eq!(events.load(Ordering::Relaxed), 2);
+
+    // Cache miss only, as compilation fails
+    script
+        .run_script_detailed("detailed_syntax", &mut (), "broken", ())
+        .unwrap_err();
+    assert_eq!(events.load(Ordering::Relaxed), 3);
+    assert!(!script
+        .cached_scripts()
+        .contains(&dir.join("detailed_syntax.rhai")));
+}
+
+#[test]
+fn blob_length_is_limited_by_max_input_size() {
+    let (mut script, _) = scripts(&[("blob.rhai", "fn on_blob() { this.push(0); this.len() }")]);
+    script.max_input_size = Some(4);
+
+    let mut blob: Blob = vec![1, 2, 3];
+    let value = script
+        .run_script_with_blob("blob", &mut blob, "on_blob", ())
+        .unwrap();
+    assert_eq!(value, json!(4));
+    assert_eq!(blob, [1, 2, 3, 0]);
+
+    let mut blob: Blob = vec![1, 2, 3, 4, 5];
+    let err = script
+        .run_script_with_blob("blob", &mut blob, "on_blob", ())
+        .unwrap_err();
+    assert!(
+        matches!(*err, EvalAltResult::ErrorDataTooLarge(..)),
+        "{err}"
+    );
+    assert_eq!(blob, [1, 2, 3, 4, 5]);
+}
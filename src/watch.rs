@@ -0,0 +1,176 @@
+//! Watch mode for script development: re-run a script whenever its file changes.
+//!
+//! Requires the `hot-reload` feature.
+
+use super::*;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Handle to a script file being watched via [`RhaiScript::watch_script`].
+///
+/// Watching stops when the handle is [stopped][ScriptWatch::stop] or dropped, or when the
+/// result callback returns `false`.
+///
+/// Requires the `hot-reload` feature.
+pub struct ScriptWatch {
+    /// File system watcher, dropped to stop watching.
+    watcher: Option<RecommendedWatcher>,
+    /// Thread re-running the script on changes.
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Debug for ScriptWatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptWatch")
+            .field("watching", &self.is_watching())
+            .finish()
+    }
+}
+
+impl ScriptWatch {
+    /// Is the script file still being watched?
+    #[must_use]
+    pub fn is_watching(&self) -> bool {
+        self.thread.as_ref().map_or(false, |t| !t.is_finished())
+    }
+    /// Stop watching, waiting for any script run in progress to complete.
+    #[inline(always)]
+    pub fn stop(self) {
+        drop(self);
+    }
+    /// Block the current thread until watching stops because the result callback returned
+    /// `false`.
+    pub fn join(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ScriptWatch {
+    fn drop(&mut self) {
+        // Dropping the watcher closes the channel of events, which ends the thread
+        drop(self.watcher.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl RhaiScript {
+    /// Watch a script file, recompiling and re-running a function in it with sample data
+    /// whenever the file changes.
+    ///
+    /// The directory of the script file is watched for file system events (via [`notify`]), so
+    /// that editors saving by replacing the file are also detected. Events arriving within
+    /// `debounce` of each other (e.g. from a single save) trigger only one re-run.
+    ///
+    /// The function is run once at the start, then after each change, with a fresh copy of
+    /// `data` each time, on a background thread. Each result is passed to `on_result`; return
+    /// `false` from it to stop watching. Watching also stops when the returned [`ScriptWatch`]
+    /// handle is stopped or dropped.
+    ///
+    /// This is meant for development tasks (e.g. a `cargo loco task`) rather than request
+    /// handlers.
+    ///
+    /// Requires the `hot-reload` feature.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let watch = script.watch_script("on_login", sample, "login", Duration::from_millis(100), |result| {
+    ///     match result {
+    ///         Ok(value) => println!("{value:#}"),
+    ///         Err(err) => eprintln!("{err}"),
+    ///     }
+    ///     true
+    /// })?;
+    ///
+    /// watch.join();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Error if the directory of the script file cannot be watched.
+    pub fn watch_script<T>(
+        &self,
+        script_file: &str,
+        data: T,
+        fn_name: &str,
+        debounce: Duration,
+        mut on_result: impl FnMut(RhaiResult<Value>) -> bool + Send + 'static,
+    ) -> Result<ScriptWatch>
+    where
+        T: Serialize + DeserializeOwned + Debug + Clone + Send + 'static,
+    {
+        let path = self.script_path(script_file);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = path.file_name().map(ToOwned::to_owned);
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)
+            .map_err(|err| Error::string(&format!("cannot watch script `{script_file}`: {err}")))?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|err| Error::string(&format!("cannot watch script `{script_file}`: {err}")))?;
+
+        let is_change = move |event: &notify::Result<notify::Event>| match event {
+            Ok(event) => {
+                !matches!(event.kind, EventKind::Access(_))
+                    && event
+                        .paths
+                        .iter()
+                        .any(|p| p.file_name().map(ToOwned::to_owned) == file_name)
+            }
+            Err(_) => false,
+        };
+
+        let script = self.clone();
+        let script_file = script_file.to_string();
+        let fn_name = fn_name.to_string();
+
+        let thread = std::thread::spawn(move || {
+            let mut run = || {
+                let _ = script.invalidate(&script_file);
+                let mut data = data.clone();
+                on_result(script.run_script(&script_file, &mut data, &fn_name, ()))
+            };
+
+            if !run() {
+                return;
+            }
+
+            // Ends when the watcher is dropped
+            while let Ok(event) = rx.recv() {
+                if !is_change(&event) {
+                    continue;
+                }
+
+                // Coalesce the burst of events from a single save
+                loop {
+                    match rx.recv_timeout(debounce) {
+                        Ok(_) => (),
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+
+                debug!(target: ROOT, script = script_file.as_str(), ?path, "script changed, re-run");
+
+                if !run() {
+                    return;
+                }
+            }
+        });
+
+        Ok(ScriptWatch {
+            watcher: Some(watcher),
+            thread: Some(thread),
+        })
+    }
+}